@@ -2,12 +2,13 @@
 
 use std::sync::Arc;
 
-use axum::{Json, Router, routing::get};
-use sysinfo::System;
+use axum::Router;
 use tokio::net::TcpListener;
 mod models;
 mod utils;
-use utils::{config, db, log::Logger, watchtower::Watchtower};
+use utils::{
+    config, db, log::Logger, mqtt::Mqtt, notifier::Notifier, syslog::Syslog, watchtower::Watchtower,
+};
 mod routes;
 use tower_http::cors::{Any, CorsLayer};
 
@@ -16,10 +17,15 @@ async fn main() {
     let mut app_config = config::EnvConfig::new();
     app_config.read_config();
 
-    let mut alerts_config = config::AlertConfig::new(&app_config);
-    alerts_config.read_config().await;
+    let config_state = config::ConfigState::read_config(&app_config).await;
 
-    let logger = Logger::new(&app_config.log_file_path).unwrap();
+    let mut logger = Logger::new(&app_config).unwrap();
+    if let Some(url) = &app_config.syslog_url {
+        match Syslog::new(url, &app_config.syslog_facility) {
+            Ok(syslog) => logger = logger.with_syslog(syslog, app_config.syslog_only),
+            Err(e) => eprintln!("Failed to set up syslog: {}", e),
+        }
+    }
 
     let pool = match db::connect(app_config.db_file_path.as_str()).await {
         Ok(pool) => Arc::new(pool),
@@ -31,33 +37,35 @@ async fn main() {
 
     db::init_db(&pool, app_config.retention_period).await;
 
+    let mqtt = Mqtt::connect(&app_config);
+
     let collector_db = pool.clone();
+    let collector_mqtt = mqtt.clone();
 
     tokio::spawn(async move {
-        let mut collector = utils::collector::MetricsCollector::new(collector_db);
+        let mut collector = utils::collector::MetricsCollector::new(collector_db, collector_mqtt);
         loop {
             collector.collect_metrics().await;
             tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
         }
     });
 
-    let wt_env = app_config.clone();
+    let notifier = Notifier::new(pool.clone(), &app_config, logger.clone());
 
-    let mut wt = Watchtower::new(pool.clone(), wt_env, logger.clone());
+    let mut wt = Watchtower::new(
+        pool.clone(),
+        config_state.clone(),
+        logger.clone(),
+        notifier.clone(),
+        mqtt,
+    );
     tokio::spawn(async move {
         wt.watch().await;
     });
 
-    let cors = if app_config.domain.is_some() {
+    let cors = if let Some(domain) = &app_config.domain {
         CorsLayer::new()
-            .allow_origin(
-                app_config
-                    .domain
-                    .as_ref()
-                    .unwrap()
-                    .parse::<axum::http::HeaderValue>()
-                    .unwrap(),
-            )
+            .allow_origin(domain.parse::<axum::http::HeaderValue>().unwrap())
             .allow_methods(Any)
             .allow_headers(Any)
     } else {
@@ -68,23 +76,24 @@ async fn main() {
     };
 
     let app = Router::new()
-        .route(
-            "/health",
-            get(|| async {
-                Json(
-                        format!(
-                            "{{\"status\": \"ok\", \"version\": \"{}\", \"hostname\": \"{}\", \"uptime\": {}}}",
-                            env!("CARGO_PKG_VERSION"),
-                            System::host_name().unwrap_or("unknown".to_string()),
-                            System::uptime()
-                        )
-                )
-            }),
-        )
+        .merge(routes::health::get_routes())
         .nest("/metrics", routes::metrics::get_routes())
+        .nest("/silences", routes::silences::get_routes())
+        .nest("/deliveries", routes::deliveries::get_routes())
+        .merge(routes::history::get_routes())
         .with_state(pool.clone())
         .nest("/alerts", routes::alerts::get_routes())
-        .with_state(alerts_config)
+        .nest("/routing", routes::routing::get_routes())
+        .nest("/channels", routes::channels::get_routes())
+        .nest("/escalations", routes::escalations::get_routes())
+        .with_state(config_state.clone())
+        .merge(routes::incidents::get_routes())
+        .with_state(routes::incidents::IncidentState {
+            pool: pool.clone(),
+            config: config_state.clone(),
+            logger: logger.clone(),
+            notifier,
+        })
         .nest("/logs", routes::logs::get_routes())
         .with_state(logger.clone())
         .merge(routes::index::get_routes())
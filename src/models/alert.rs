@@ -4,11 +4,17 @@
 // | `alerts[].metric_id`             | Enum                                   | The metric to monitor. |
 // | `alerts[].logic`                 | `"eq" \| "gt" \| "lt" \| "gte" \| "lte"`    | Logical comparison operator for the alert condition. |
 // | `alerts[].value`                 | Number                                   | Threshold value for triggering an alert. |
-// | `alerts[].request`               | Object                                   | HTTP request details for triggered alerts. |
+// | `alerts[].condition`             | Object (optional)                        | Expression tree (`and`, `or`, `not`, `threshold`, `rate`, `delta`, `forecast`, `anomaly`, `absence`) used instead of `metric_id`/`logic`/`value`. |
+// | `alerts[].labels`                | Object (optional)                        | Free-form key/value pairs used to match silences and routes. |
+// | `alerts[].severity`              | `"info" \| "warning" \| "critical"` or any string | Severity used in logs, placeholders and routing, defaults to `"warning"`. |
+// | `alerts[].channels`              | Array (optional)                         | Ids of notification channels to notify. |
+// | `alerts[].escalation_policy`     | Uuid (optional)                          | Escalation policy notified while the alert stays unacknowledged. |
+// | `alerts[].request`               | Object (optional)                        | Inline HTTP request, kept for rules written before channels existed. |
+// | `alerts[].exec`                  | Object (optional)                        | Local command run once per incident, see `Exec`. |
 
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display};
 
-use super::request::Request;
+use super::{exec::Exec, metrics, request::Request};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -17,10 +23,36 @@ use uuid::Uuid;
 pub struct Alert {
     #[serde(default = "uuid::Uuid::new_v4")]
     pub id: Uuid,
+    #[serde(default)]
     pub metric_id: String,
+    #[serde(default)]
     pub logic: Logic,
+    #[serde(default)]
     pub value: String,
-    pub request: Request,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub severity: Severity,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalation_policy: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Request>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exec: Option<Exec>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default)]
+#[serde(from = "String", into = "String")]
+pub enum Severity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+    Custom(String),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
@@ -33,6 +65,108 @@ pub enum Logic {
     Lte,
 }
 
+/// Example JSON:
+/// {
+///     "and": [
+///         { "threshold": { "metric_id": "cpu_usage_percentage", "logic": "Gt", "value": "90" } },
+///         { "or": [
+///             { "threshold": { "metric_id": "mem_usage_percentage", "logic": "Gt", "value": "80" } },
+///             { "not": { "threshold": { "metric_id": "disk_free", "logic": "Gt", "value": "1024" } } }
+///         ] }
+///     ]
+/// }
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Condition {
+    Threshold(Threshold),
+    Rate(Change),
+    Delta(Change),
+    Forecast(Forecast),
+    Anomaly(Anomaly),
+    Absence(Absence),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Threshold {
+    pub metric_id: String,
+    pub logic: Logic,
+    pub value: String,
+}
+
+/// Compares how much a metric moved over the last `window` seconds.
+/// `delta` is the total change, `rate` the average change per second.
+///
+/// Example JSON:
+/// { "delta": { "metric_id": "disk_free", "window": 600, "logic": "Lt", "value": "-5120" } }
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Change {
+    pub metric_id: String,
+    pub window: u64,
+    pub logic: Logic,
+    pub value: String,
+}
+
+/// Compares the hours left until the linear trend of a metric over the last `window`
+/// seconds reaches `target`. For `disk_*` metrics `mount_point` selects a specific mount.
+///
+/// Example JSON:
+/// { "forecast": { "metric_id": "disk_free", "target": "0", "window": 21600, "logic": "Lt", "value": "24" } }
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Forecast {
+    pub metric_id: String,
+    pub target: String,
+    pub window: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mount_point: Option<String>,
+    pub logic: Logic,
+    pub value: String,
+}
+
+/// Fires when the latest value deviates more than `sigma` standard deviations from the
+/// mean of the last `window` seconds. With `seasonal` the baseline is taken from the same
+/// `window` at the same time of day over the past week instead.
+///
+/// Example JSON:
+/// { "anomaly": { "metric_id": "cpu_usage_percentage", "window": 3600, "sigma": 3.0, "seasonal": false } }
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Anomaly {
+    pub metric_id: String,
+    pub window: u64,
+    pub sigma: f32,
+    #[serde(default)]
+    pub seasonal: bool,
+}
+
+/// Fires when no row has been stored for the metric's table (or custom metric name)
+/// during the last `window` seconds.
+///
+/// Example JSON:
+/// { "absence": { "metric_id": "custom_backup_heartbeat", "window": 90000 } }
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Absence {
+    pub metric_id: String,
+    pub window: u64,
+}
+
+// Metric value seen while evaluating a condition, used to fill in request placeholders
+#[derive(Serialize, Clone, Debug)]
+pub struct Observation {
+    pub metric_id: String,
+    pub value: f32,
+    pub baseline: Option<Baseline>,
+}
+
+// Statistics an anomaly condition compared the observed value against
+#[derive(Serialize, Clone, Debug)]
+pub struct Baseline {
+    pub mean: f32,
+    pub stddev: f32,
+    pub zscore: f32,
+}
+
 impl Display for Logic {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -45,16 +179,133 @@ impl Display for Logic {
     }
 }
 
-impl Display for Alert {
+impl From<String> for Severity {
+    fn from(s: String) -> Self {
+        match s.to_lowercase().as_str() {
+            "info" => Severity::Info,
+            "warning" => Severity::Warning,
+            "critical" => Severity::Critical,
+            _ => Severity::Custom(s),
+        }
+    }
+}
+
+impl From<Severity> for String {
+    fn from(severity: Severity) -> Self {
+        severity.to_string()
+    }
+}
+
+impl Display for Severity {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Critical => write!(f, "critical"),
+            Severity::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl Display for Threshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.metric_id, self.logic, self.value)
+    }
+}
+
+impl Display for Forecast {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "hours_until({}", self.metric_id)?;
+        if let Some(mount_point) = &self.mount_point {
+            write!(f, " on {}", mount_point)?;
+        }
         write!(
             f,
-            "Alert metric_id: {} {} {}, {}",
-            self.metric_id, self.logic, self.value, self.request
+            " reaches {} [{}s]) {} {}",
+            self.target, self.window, self.logic, self.value
         )
     }
 }
 
+impl Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "|zscore({}[{}s]", self.metric_id, self.window)?;
+        if self.seasonal {
+            write!(f, " seasonal")?;
+        }
+        write!(f, ")| > {}", self.sigma)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Threshold(threshold) => write!(f, "{}", threshold),
+            Condition::Rate(change) => write_change(f, "rate", change),
+            Condition::Delta(change) => write_change(f, "delta", change),
+            Condition::Forecast(forecast) => write!(f, "{}", forecast),
+            Condition::Anomaly(anomaly) => write!(f, "{}", anomaly),
+            Condition::Absence(absence) => {
+                write!(f, "no_data({}) for {}s", absence.metric_id, absence.window)
+            }
+            Condition::And(conditions) => write_group(f, conditions, " AND "),
+            Condition::Or(conditions) => write_group(f, conditions, " OR "),
+            Condition::Not(condition) => {
+                write!(f, "NOT ")?;
+                write_operand(f, condition)
+            }
+        }
+    }
+}
+
+fn write_change(f: &mut std::fmt::Formatter<'_>, name: &str, change: &Change) -> std::fmt::Result {
+    write!(
+        f,
+        "{}({}[{}s]) {} {}",
+        name, change.metric_id, change.window, change.logic, change.value
+    )
+}
+
+fn write_group(
+    f: &mut std::fmt::Formatter<'_>,
+    conditions: &[Condition],
+    separator: &str,
+) -> std::fmt::Result {
+    for (i, condition) in conditions.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        write_operand(f, condition)?;
+    }
+    Ok(())
+}
+
+// Nested groups are wrapped in parentheses so the precedence stays readable
+fn write_operand(f: &mut std::fmt::Formatter<'_>, condition: &Condition) -> std::fmt::Result {
+    match condition {
+        Condition::And(_) | Condition::Or(_) => write!(f, "({})", condition),
+        _ => write!(f, "{}", condition),
+    }
+}
+
+impl Display for Alert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}] Alert condition: {}",
+            self.severity,
+            self.condition()
+        )?;
+        if let Some(request) = &self.request {
+            write!(f, ", {}", request)?;
+        }
+        if let Some(exec) = &self.exec {
+            write!(f, ", {}", exec)?;
+        }
+        Ok(())
+    }
+}
+
 impl Logic {
     pub fn check<T>(&self, value: T, threshold: T) -> bool
     where
@@ -69,3 +320,260 @@ impl Logic {
         }
     }
 }
+
+impl Condition {
+    // Every metric id referenced anywhere in the expression tree
+    pub fn metric_ids(&self) -> Vec<String> {
+        match self {
+            Condition::Threshold(threshold) => vec![threshold.metric_id.clone()],
+            Condition::Rate(change) | Condition::Delta(change) => vec![change.metric_id.clone()],
+            Condition::Forecast(forecast) => vec![forecast.metric_id.clone()],
+            Condition::Anomaly(anomaly) => vec![anomaly.metric_id.clone()],
+            Condition::Absence(absence) => vec![absence.metric_id.clone()],
+            Condition::And(conditions) | Condition::Or(conditions) => {
+                conditions.iter().flat_map(|c| c.metric_ids()).collect()
+            }
+            Condition::Not(condition) => condition.metric_ids(),
+        }
+    }
+
+    // Comparison and limit of single value conditions, `None` for anomalies, absences and groups
+    pub fn limit(&self) -> Option<(&Logic, &str)> {
+        match self {
+            Condition::Threshold(threshold) => Some((&threshold.logic, &threshold.value)),
+            Condition::Rate(change) | Condition::Delta(change) => {
+                Some((&change.logic, &change.value))
+            }
+            Condition::Forecast(forecast) => Some((&forecast.logic, &forecast.value)),
+            _ => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Condition::Threshold(threshold) => threshold.validate(),
+            Condition::Rate(change) | Condition::Delta(change) => change.validate(),
+            Condition::Forecast(forecast) => forecast.validate(),
+            Condition::Anomaly(anomaly) => anomaly.validate(),
+            Condition::Absence(absence) => absence.validate(),
+            Condition::And(conditions) | Condition::Or(conditions) => {
+                if conditions.is_empty() {
+                    return Err("Condition groups must contain at least one condition".to_string());
+                }
+                conditions.iter().try_for_each(|c| c.validate())
+            }
+            Condition::Not(condition) => condition.validate(),
+        }
+    }
+}
+
+impl Threshold {
+    pub fn validate(&self) -> Result<(), String> {
+        if metrics::get_metric_type_from_str(&self.metric_id).is_none() {
+            return Err(format!("Invalid metric id: {}", self.metric_id));
+        }
+        self.value
+            .parse::<f32>()
+            .map(|_| ())
+            .map_err(|_| format!("Failed to parse value: {} as f32", self.value))
+    }
+}
+
+impl Change {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window == 0 {
+            return Err("Window must be at least 1 second".to_string());
+        }
+        Threshold {
+            metric_id: self.metric_id.clone(),
+            logic: self.logic.clone(),
+            value: self.value.clone(),
+        }
+        .validate()
+    }
+}
+
+impl Forecast {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window == 0 {
+            return Err("Window must be at least 1 second".to_string());
+        }
+        if self.mount_point.is_some() && !self.metric_id.starts_with("disk_") {
+            return Err("Mount points can only be used with disk metrics".to_string());
+        }
+        self.target
+            .parse::<f32>()
+            .map_err(|_| format!("Failed to parse target: {} as f32", self.target))?;
+        Threshold {
+            metric_id: self.metric_id.clone(),
+            logic: self.logic.clone(),
+            value: self.value.clone(),
+        }
+        .validate()
+    }
+}
+
+impl Anomaly {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window == 0 {
+            return Err("Window must be at least 1 second".to_string());
+        }
+        if self.sigma <= 0.0 {
+            return Err("Sigma must be greater than 0".to_string());
+        }
+        if metrics::get_metric_type_from_str(&self.metric_id).is_none() {
+            return Err(format!("Invalid metric id: {}", self.metric_id));
+        }
+        Ok(())
+    }
+}
+
+impl Absence {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window == 0 {
+            return Err("Window must be at least 1 second".to_string());
+        }
+        if metrics::get_metric_type_from_str(&self.metric_id).is_none() {
+            return Err(format!("Invalid metric id: {}", self.metric_id));
+        }
+        Ok(())
+    }
+}
+
+impl Alert {
+    // Alerts without an explicit condition fall back to the single metric_id/logic/value triple
+    pub fn condition(&self) -> Condition {
+        match &self.condition {
+            Some(condition) => condition.clone(),
+            None => Condition::Threshold(Threshold {
+                metric_id: self.metric_id.clone(),
+                logic: self.logic.clone(),
+                value: self.value.clone(),
+            }),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.condition().validate()?;
+        if let Some(request) = &self.request {
+            request.validate()?;
+        }
+        match &self.exec {
+            Some(exec) => exec.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn threshold(metric_id: &str, logic: Logic, value: &str) -> Condition {
+        Condition::Threshold(Threshold {
+            metric_id: metric_id.to_string(),
+            logic,
+            value: value.to_string(),
+        })
+    }
+
+    #[test]
+    fn empty_groups_are_rejected() {
+        assert!(Condition::And(vec![]).validate().is_err());
+        assert!(Condition::Or(vec![]).validate().is_err());
+        let nested = Condition::Not(Box::new(Condition::Or(vec![])));
+        assert!(nested.validate().is_err());
+    }
+
+    #[test]
+    fn nested_conditions_are_validated() {
+        let valid = Condition::And(vec![
+            threshold("cpu_usage_percentage", Logic::Gt, "90"),
+            Condition::Not(Box::new(threshold("disk_free", Logic::Gt, "1024"))),
+        ]);
+        assert!(valid.validate().is_ok());
+
+        let invalid = Condition::And(vec![
+            threshold("cpu_usage_percentage", Logic::Gt, "90"),
+            Condition::Not(Box::new(threshold("unknown_metric", Logic::Gt, "1"))),
+        ]);
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn display_wraps_nested_groups() {
+        let condition = Condition::And(vec![
+            threshold("cpu_usage_percentage", Logic::Gt, "90"),
+            Condition::Or(vec![
+                threshold("mem_usage_percentage", Logic::Gt, "80"),
+                Condition::Not(Box::new(threshold("disk_free", Logic::Gt, "1024"))),
+            ]),
+        ]);
+        assert_eq!(
+            condition.to_string(),
+            "cpu_usage_percentage > 90 AND (mem_usage_percentage > 80 OR NOT disk_free > 1024)"
+        );
+        let negated_group = Condition::Not(Box::new(Condition::And(vec![
+            threshold("cpu_usage_percentage", Logic::Gt, "90"),
+            threshold("mem_usage_percentage", Logic::Lte, "10"),
+        ])));
+        assert_eq!(
+            negated_group.to_string(),
+            "NOT (cpu_usage_percentage > 90 AND mem_usage_percentage <= 10)"
+        );
+    }
+
+    #[test]
+    fn condition_round_trips_through_json() {
+        let json = r#"{
+            "and": [
+                { "threshold": { "metric_id": "cpu_usage_percentage", "logic": "Gt", "value": "90" } },
+                { "not": { "threshold": { "metric_id": "disk_free", "logic": "Gt", "value": "1024" } } }
+            ]
+        }"#;
+        let condition: Condition = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            condition,
+            Condition::And(vec![
+                threshold("cpu_usage_percentage", Logic::Gt, "90"),
+                Condition::Not(Box::new(threshold("disk_free", Logic::Gt, "1024"))),
+            ])
+        );
+        let serialized = serde_json::to_string(&condition).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Condition>(&serialized).unwrap(),
+            condition
+        );
+    }
+
+    #[test]
+    fn legacy_flat_alert_round_trips() {
+        let json = r#"{
+            "id": "6f1c2a3e-8d4b-4c1a-9e2f-0a1b2c3d4e5f",
+            "metric_id": "cpu_usage_percentage",
+            "logic": "Gt",
+            "value": "90",
+            "request": {
+                "request_type": "POST",
+                "url": "http://example.com/hook",
+                "body": { "format": "json", "payload": "{\"text\": \"cpu\"}" }
+            }
+        }"#;
+        let alert: Alert = serde_json::from_str(json).unwrap();
+        assert!(alert.validate().is_ok());
+        assert_eq!(alert.condition, None);
+        assert_eq!(alert.severity, Severity::Warning);
+        assert_eq!(
+            alert.condition(),
+            threshold("cpu_usage_percentage", Logic::Gt, "90")
+        );
+
+        let serialized = serde_json::to_value(&alert).unwrap();
+        assert_eq!(serialized["metric_id"], "cpu_usage_percentage");
+        assert_eq!(serialized["logic"], "Gt");
+        assert_eq!(serialized["value"], "90");
+        assert!(serialized.get("condition").is_none());
+        assert!(serialized.get("channels").is_none());
+        assert_eq!(serde_json::from_value::<Alert>(serialized).unwrap(), alert);
+    }
+}
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    alert::{Alert, Observation},
+    incident::AlertState,
+};
+
+/// Body of `POST /alerts/backtest`, replaying `alert` over stored metrics.
+///
+/// Example:
+/// { "alert": { "metric_id": "cpu_usage_percentage", "logic": "Gt", "value": "90" },
+///   "since": 1760000000, "until": 1760086400, "step": 5 }
+///
+/// `step` is the seconds between evaluations and defaults to the watchtower interval.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BacktestRequest {
+    pub alert: Alert,
+    pub since: i64,
+    pub until: i64,
+    #[serde(default)]
+    pub step: Option<u64>,
+}
+
+// The rule would have started firing or resolved at `timestamp`
+#[derive(Serialize, Clone, Debug)]
+pub struct BacktestEvent {
+    pub state: AlertState,
+    pub timestamp: i64,
+    pub observations: Vec<Observation>,
+}
+
+// `firing_seconds` only counts incidents that resolved within the range
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Backtest {
+    pub events: Vec<BacktestEvent>,
+    pub firings: u32,
+    pub firing_seconds: i64,
+    pub evaluations: u32,
+}
@@ -0,0 +1,336 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use lettre::message::Mailbox;
+
+use super::{exec::Exec, request::Request};
+use crate::utils::template;
+
+/// A named notification target that alerts and routes reference by id.
+///
+/// Example JSON:
+/// {
+///     "id": "0b7d3f4e-8f5e-4a0e-9a53-3c1b1d4a8f21",
+///     "name": "ops-chat",
+///     "kind": {
+///         "webhook": { "request_type": "post", "url": "https://chat.example.com", "body": { "format": "json", "payload": "{severity}: {metric}" } }
+///     }
+/// }
+///
+/// Chat services have native kinds that format the message themselves:
+/// "kind": { "slack": { "webhook_url": "https://hooks.slack.com/services/T000/B000/XXXX" } }
+/// "kind": { "discord": { "webhook_url": "https://discord.com/api/webhooks/1234/abcd" } }
+///
+/// As do the push services:
+/// "kind": { "telegram": { "bot_token": "123456:ABC-DEF", "chat_id": "-1001234567890" } }
+/// "kind": { "ntfy": { "topic": "ops-alerts", "tags": ["server"] } }
+/// "kind": { "gotify": { "server": "https://gotify.example.com", "token": "AbCdEf123" } }
+/// "kind": { "matrix": { "homeserver": "https://matrix.example.com", "access_token": "syt_abc", "room_id": "!ops:example.com" } }
+/// "kind": { "teams": { "webhook_url": "https://example.webhook.office.com/webhookb2/..." } }
+///
+/// Incident services get trigger, acknowledge and resolve events keyed by the alert id:
+/// "kind": { "pagerduty": { "routing_key": "R0ABCDEF0123456789ABCDEF01234567" } }
+///
+/// Commands run on the host instead, see `Exec`:
+/// "kind": { "exec": { "command": "docker", "args": ["system", "prune", "-f"] } }
+///
+/// And email, batching the alerts of one check into a single message:
+/// "kind": { "email": { "host": "smtp.example.com", "username": "alerts", "password": "secret",
+///     "from": "sciigilo <alerts@example.com>", "to": ["ops@example.com"] } }
+///
+/// Channels notify once when an incident opens and once when it resolves. Plain webhooks
+/// are the exception and keep firing on every check. `"repeat_interval": 3600` sends a
+/// reminder every hour while the incident stays open.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Channel {
+    #[serde(default = "uuid::Uuid::new_v4")]
+    pub id: Uuid,
+    pub name: String,
+    pub kind: ChannelKind,
+    // Seconds between reminders while an incident stays open, none by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_interval: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ChannelKind {
+    Webhook(Request),
+    Slack(Slack),
+    Discord(Discord),
+    Telegram(Telegram),
+    Ntfy(Ntfy),
+    Gotify(Gotify),
+    Matrix(Matrix),
+    Teams(Teams),
+    PagerDuty(PagerDuty),
+    Email(Email),
+    Exec(Exec),
+}
+
+// Slack incoming webhook, `channel` and `username` override the webhook defaults
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Slack {
+    pub webhook_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Discord {
+    pub webhook_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+impl Default for ChannelKind {
+    fn default() -> Self {
+        ChannelKind::Webhook(Request::default())
+    }
+}
+
+// `api_url` only needs changing for a local Bot API server
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Telegram {
+    pub bot_token: String,
+    pub chat_id: String,
+    #[serde(default = "default_telegram_api")]
+    pub api_url: String,
+}
+
+// `priority` (1-5) overrides the one derived from the severity
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Ntfy {
+    #[serde(default = "default_ntfy_server")]
+    pub server: String,
+    pub topic: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+// `priority` (0-10) overrides the one derived from the severity
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Gotify {
+    pub server: String,
+    pub token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+}
+
+// Messages are sent as the user owning `access_token`, which must already have joined the room
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Matrix {
+    pub homeserver: String,
+    pub access_token: String,
+    pub room_id: String,
+}
+
+// Teams incoming webhook, messages are sent as Adaptive Cards
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Teams {
+    pub webhook_url: String,
+}
+
+// Events API v2, `url` can point at any compatible endpoint such as Opsgenie's integration
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct PagerDuty {
+    #[serde(default = "default_pagerduty_url")]
+    pub url: String,
+    pub routing_key: String,
+    // Shown as the affected system, defaults to the hostname
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+// `subject`, `text` and `html` are templates over `alerts`, `hostname` and `dashboard_url`,
+// each entry of `alerts` has the same variables as a request template
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Email {
+    pub host: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    None,
+    #[default]
+    Starttls,
+    Implicit,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_pagerduty_url() -> String {
+    "https://events.pagerduty.com/v2/enqueue".to_string()
+}
+
+fn default_telegram_api() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+impl Channel {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Channel name must not be empty".to_string());
+        }
+        if self.repeat_interval == Some(0) {
+            return Err("Repeat interval must be at least 1 second".to_string());
+        }
+        match &self.kind {
+            ChannelKind::Webhook(request) => request.validate(),
+            ChannelKind::Slack(Slack { webhook_url, .. })
+            | ChannelKind::Discord(Discord { webhook_url, .. })
+            | ChannelKind::Teams(Teams { webhook_url }) => validate_url(webhook_url),
+            ChannelKind::Telegram(telegram) => {
+                if telegram.bot_token.is_empty() || telegram.chat_id.is_empty() {
+                    return Err("Telegram channels need a bot token and a chat id".to_string());
+                }
+                validate_url(&telegram.api_url)
+            }
+            ChannelKind::Ntfy(ntfy) => {
+                if ntfy.topic.is_empty() {
+                    return Err("ntfy channels need a topic".to_string());
+                }
+                if ntfy.priority.is_some_and(|p| !(1..=5).contains(&p)) {
+                    return Err("ntfy priorities range from 1 to 5".to_string());
+                }
+                validate_url(&ntfy.server)
+            }
+            ChannelKind::Gotify(gotify) => {
+                if gotify.token.is_empty() {
+                    return Err("Gotify channels need an application token".to_string());
+                }
+                if gotify.priority.is_some_and(|p| p > 10) {
+                    return Err("Gotify priorities range from 0 to 10".to_string());
+                }
+                validate_url(&gotify.server)
+            }
+            ChannelKind::Matrix(matrix) => {
+                if matrix.access_token.is_empty() {
+                    return Err("Matrix channels need an access token".to_string());
+                }
+                if !matrix.room_id.starts_with('!') {
+                    return Err(format!(
+                        "Matrix room ids start with '!', got {:?}",
+                        matrix.room_id
+                    ));
+                }
+                validate_url(&matrix.homeserver)
+            }
+            ChannelKind::PagerDuty(pagerduty) => {
+                if pagerduty.routing_key.is_empty() {
+                    return Err("PagerDuty channels need a routing key".to_string());
+                }
+                validate_url(&pagerduty.url)
+            }
+            ChannelKind::Email(email) => email.validate(),
+            ChannelKind::Exec(exec) => exec.validate(),
+        }
+    }
+}
+
+impl ChannelKind {
+    // Plain webhooks only ever announced problems, native kinds also report the recovery
+    pub fn sends_resolved(&self) -> bool {
+        !matches!(self, ChannelKind::Webhook(_) | ChannelKind::Exec(_))
+    }
+
+    // Plain webhooks keep firing on every check while the alert fires, like they always did.
+    // Everything else is notified once per incident, plus the channel's `repeat_interval`.
+    pub fn repeats_every_check(&self) -> bool {
+        matches!(self, ChannelKind::Webhook(_))
+    }
+
+    // Incident services track acknowledgements themselves and are told about them
+    pub fn sends_acknowledged(&self) -> bool {
+        matches!(self, ChannelKind::PagerDuty(_))
+    }
+}
+
+impl Email {
+    fn validate(&self) -> Result<(), String> {
+        if self.host.is_empty() {
+            return Err("Email channels need an SMTP host".to_string());
+        }
+        if self.to.is_empty() {
+            return Err("Email channels need at least one recipient".to_string());
+        }
+        for address in std::iter::once(&self.from).chain(&self.to) {
+            if address.parse::<Mailbox>().is_err() {
+                return Err(format!("Invalid email address: {}", address));
+            }
+        }
+        for text in [&self.subject, &self.text, &self.html]
+            .into_iter()
+            .flatten()
+        {
+            template::validate(text)?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_url(url: &str) -> Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Invalid url {}: {}", url, e)),
+    }
+}
+
+impl Display for ChannelKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelKind::Webhook(request) => write!(f, "{}", request),
+            ChannelKind::Slack(slack) => write!(f, "Slack webhook {}", slack.webhook_url),
+            ChannelKind::Discord(discord) => write!(f, "Discord webhook {}", discord.webhook_url),
+            ChannelKind::Telegram(telegram) => write!(f, "Telegram chat {}", telegram.chat_id),
+            ChannelKind::Ntfy(ntfy) => write!(f, "ntfy topic {} on {}", ntfy.topic, ntfy.server),
+            ChannelKind::Gotify(gotify) => write!(f, "Gotify on {}", gotify.server),
+            ChannelKind::Matrix(matrix) => {
+                write!(f, "Matrix room {} on {}", matrix.room_id, matrix.homeserver)
+            }
+            ChannelKind::Teams(teams) => write!(f, "Teams webhook {}", teams.webhook_url),
+            ChannelKind::PagerDuty(pagerduty) => write!(f, "PagerDuty events to {}", pagerduty.url),
+            ChannelKind::Email(email) => {
+                write!(f, "Email to {} via {}", email.to.join(", "), email.host)
+            }
+            ChannelKind::Exec(exec) => write!(f, "{}", exec),
+        }
+    }
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Channel {} ({})", self.name, self.kind)
+    }
+}
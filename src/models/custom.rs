@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+// **Custom Metrics**
+//   - Values pushed by external jobs, e.g. heartbeats or queue sizes
+//   - Referenced in alerts as `custom_<name>`
+
+#[derive(Serialize, Deserialize, Default, PartialEq, Clone)]
+#[serde(rename_all = "lowercase")]
+pub struct CustomMetric {
+    pub name: String,
+    #[serde(default)]
+    pub value: f32,
+}
+
+impl CustomMetric {
+    pub fn new(name: String, value: f32) -> CustomMetric {
+        CustomMetric { name, value }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty()
+            || !self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!(
+                "Invalid custom metric name: {}, only letters, digits, '_' and '-' are allowed",
+                self.name
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Display for CustomMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Custom metric {}: {}", self.name, self.value)
+    }
+}
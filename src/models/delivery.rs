@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// One attempt at sending a notification, successful or not
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Delivery {
+    pub alert_id: Uuid,
+    pub target: String,
+    pub attempt: u32,
+    pub timestamp: i64,
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 
-use super::metrics::{Field, Metric};
+use super::metrics::{self, Field, Metric};
 
 // **Disk Metrics**
 //   - Overall disk (or partition?) usage
@@ -16,6 +16,15 @@ pub struct DiskMetrics {
     pub usage_percentage: f32,
 }
 
+#[derive(Serialize, Deserialize, Default, PartialEq, Clone)]
+pub struct DiskForecast {
+    pub mount_point: String,
+    pub total: u32,
+    pub free: u32,
+    pub free_change_per_hour: Option<f32>,
+    pub hours_until_full: Option<f32>,
+}
+
 pub enum Fields {
     Total,
     Used,
@@ -97,6 +106,24 @@ impl DiskMetrics {
     }
 }
 
+impl DiskForecast {
+    pub fn from_history(
+        mount_point: String,
+        history: &[(i64, DiskMetrics)],
+    ) -> Option<DiskForecast> {
+        let (_, latest) = history.last()?;
+        let free: Vec<(i64, f32)> = history.iter().map(|(t, m)| (*t, m.free as f32)).collect();
+        Some(DiskForecast {
+            mount_point,
+            total: latest.total,
+            free: latest.free,
+            free_change_per_hour: metrics::linear_regression(&free)
+                .map(|(slope, _)| (slope * 3600.0) as f32),
+            hours_until_full: metrics::hours_until(&free, 0.0),
+        })
+    }
+}
+
 impl Display for DiskMetrics {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
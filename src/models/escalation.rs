@@ -0,0 +1,56 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Multi-step escalation for unacknowledged alerts. Each step notifies its channels once
+/// the incident has been firing for `after` seconds without being acknowledged.
+///
+/// Example JSON:
+/// {
+///     "name": "on-call",
+///     "steps": [
+///         { "after": 0, "channels": ["0b7d3f4e-8f5e-4a0e-9a53-3c1b1d4a8f21"] },
+///         { "after": 900, "channels": ["5f3c2a10-6a8e-4c1e-b2a4-9d7e1f0c3b55"] },
+///         { "after": 3600, "channels": ["a4e1c9d2-0c3f-4b7a-8e6d-2f5b9c1a7e30"] }
+///     ]
+/// }
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct EscalationPolicy {
+    #[serde(default = "uuid::Uuid::new_v4")]
+    pub id: Uuid,
+    pub name: String,
+    pub steps: Vec<EscalationStep>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct EscalationStep {
+    pub after: u64,
+    pub channels: Vec<Uuid>,
+}
+
+impl EscalationPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Escalation policy name must not be empty".to_string());
+        }
+        if self.steps.is_empty() {
+            return Err("Escalation policies need at least one step".to_string());
+        }
+        if self.steps.windows(2).any(|w| w[1].after < w[0].after) {
+            return Err("Escalation steps must be ordered by their delay".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Display for EscalationPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Escalation policy {} ({} steps)",
+            self.name,
+            self.steps.len()
+        )
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::template;
+
+/// Runs a local command when an alert fires, e.g. to clean up a full disk.
+///
+/// Example JSON:
+/// {
+///     "exec": {
+///         "command": "docker",
+///         "args": ["system", "prune", "-f"],
+///         "timeout": 120,
+///         "run_as": "ci"
+///     }
+/// }
+///
+/// The command is started directly, without a shell. Arguments are templates with the same
+/// variables as requests, and the alert is also described in `SCIIGILO_*` environment variables.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Exec {
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    // Seconds before the command is killed
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    // User name or uid to run as, which needs sciigilo to run as root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_as: Option<String>,
+}
+
+fn default_timeout() -> u64 {
+    60
+}
+
+impl Exec {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.command.trim().is_empty() {
+            return Err("Command must not be empty".to_string());
+        }
+        if self.timeout == 0 {
+            return Err("Command timeout must be at least 1 second".to_string());
+        }
+        if self.run_as.as_ref().is_some_and(|user| user.is_empty()) {
+            return Err("run_as must not be empty".to_string());
+        }
+        self.args.iter().try_for_each(|arg| template::validate(arg))
+    }
+}
+
+impl Display for Exec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Command {}", self.command)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        if let Some(user) = &self.run_as {
+            write!(f, " as {}", user)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::incident::AlertState;
+
+// An alert starting to fire or resolving, with the observed value.
+// Incidents of deleted alerts resolve without severity, metric or value.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct AlertEvent {
+    pub alert_id: Uuid,
+    pub incident_id: Uuid,
+    pub state: AlertState,
+    pub timestamp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metric_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f32>,
+}
+
+// Every set field has to match. The aggregations ignore `state` and apply `since`/`until`
+// to the firing time (daily counts) or the resolution time (time to resolve).
+#[derive(Clone, Debug, Default)]
+pub struct HistoryFilter {
+    pub alert_id: Option<Uuid>,
+    pub metric_id: Option<String>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub state: Option<AlertState>,
+}
+
+// Number of times a rule started firing on one local day
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct DailyCount {
+    pub alert_id: Uuid,
+    pub day: String,
+    pub count: u32,
+}
+
+// Mean seconds from firing to resolution, over the incidents resolved in the range
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct ResolveTime {
+    pub alert_id: Uuid,
+    pub resolved: u32,
+    pub mean_seconds: f64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct AlertHistory {
+    pub events: Vec<AlertEvent>,
+    pub daily: Vec<DailyCount>,
+    pub time_to_resolve: Vec<ResolveTime>,
+}
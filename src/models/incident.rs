@@ -0,0 +1,97 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// One firing period of an alert, from the first failed check until it resolves.
+// `escalation_step` counts the notification steps already handled so restarts resume.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Incident {
+    pub id: Uuid,
+    pub alert_id: Uuid,
+    pub started_at: i64,
+    pub resolved_at: Option<i64>,
+    pub acknowledged_at: Option<i64>,
+    pub acknowledged_by: Option<String>,
+    pub ack_note: Option<String>,
+    pub escalation_step: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<IncidentEvent>,
+}
+
+// Whether a notification announces a new problem or the end of one
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    #[default]
+    Firing,
+    Resolved,
+}
+
+/// One entry of an incident timeline.
+///
+/// Example JSON:
+/// { "timestamp": 1760000000, "kind": "notified", "detail": "Escalation step 1 (2 targets)" }
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct IncidentEvent {
+    pub timestamp: i64,
+    pub kind: EventKind,
+    pub detail: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Fired,
+    Notified,
+    Suppressed,
+    Acknowledged,
+    Resolved,
+}
+
+impl IncidentEvent {
+    pub fn new(kind: EventKind, detail: String) -> Self {
+        IncidentEvent {
+            timestamp: chrono::Utc::now().timestamp(),
+            kind,
+            detail,
+        }
+    }
+}
+
+impl Display for AlertState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlertState::Firing => write!(f, "firing"),
+            AlertState::Resolved => write!(f, "resolved"),
+        }
+    }
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            EventKind::Fired => "fired",
+            EventKind::Notified => "notified",
+            EventKind::Suppressed => "suppressed",
+            EventKind::Acknowledged => "acknowledged",
+            EventKind::Resolved => "resolved",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+impl std::str::FromStr for EventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fired" => Ok(EventKind::Fired),
+            "notified" => Ok(EventKind::Notified),
+            "suppressed" => Ok(EventKind::Suppressed),
+            "acknowledged" => Ok(EventKind::Acknowledged),
+            "resolved" => Ok(EventKind::Resolved),
+            _ => Err(format!("Unknown incident event: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{delivery::Delivery, incident::AlertState};
+
+/// One line of the alert log, stored as JSON lines.
+///
+/// Example:
+/// {"timestamp":1760000000,"time":"2025-10-09T10:53:20+02:00","event":"firing","alert_id":"61247a75-8141-49c3-8505-a789f61d9f4c",
+///  "severity":"critical","metric_id":"cpu_usage_percentage","value":97.5,"logic":">","threshold":"90","state":"firing",
+///  "message":"Firing: [critical] Alert condition: cpu_usage_percentage > 90"}
+///
+/// `delivery` holds the final attempt of a notification for `delivery` events.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct LogEntry {
+    pub timestamp: i64,
+    #[serde(default)]
+    pub time: String,
+    pub event: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alert_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metric_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logic: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<AlertState>,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delivery: Option<Delivery>,
+}
+
+// Every condition has to match, unset ones match everything
+#[derive(Clone, Debug, Default)]
+pub struct LogFilter {
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub alert_id: Option<Uuid>,
+    pub state: Option<AlertState>,
+    pub event: Option<String>,
+}
+
+impl LogFilter {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+            && self.alert_id.is_none_or(|id| entry.alert_id == Some(id))
+            && self.state.is_none_or(|state| entry.state == Some(state))
+            && self
+                .event
+                .as_ref()
+                .is_none_or(|event| entry.event.eq_ignore_ascii_case(event))
+    }
+}
@@ -1,4 +1,4 @@
-use super::{cpu, disk, mem};
+use super::{alert::Baseline, cpu, custom, disk, mem};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
@@ -7,6 +7,7 @@ pub enum MetricType {
     Cpu(cpu::CpuMetrics),
     Memory(mem::MemoryMetrics),
     Disk(disk::DiskMetrics),
+    Custom(custom::CustomMetric),
 }
 
 pub trait Field {
@@ -51,14 +52,16 @@ impl MetricType {
                 disk::Fields::from_str(&field.replace("disk_", "")).unwrap(),
                 logic,
             ),
+            MetricType::Custom(custom) => logic.check(custom.value, threshold.into()),
         }
     }
 
     pub fn get_value(&self, field: String) -> f32 {
         match self {
-            MetricType::Cpu(cpu) => cpu.get_value(field),
-            MetricType::Memory(mem) => mem.get_value(field),
-            MetricType::Disk(disk) => disk.get_value(field),
+            MetricType::Cpu(cpu) => cpu.get_value(field.replace("cpu_", "")),
+            MetricType::Memory(mem) => mem.get_value(field.replace("mem_", "")),
+            MetricType::Disk(disk) => disk.get_value(field.replace("disk_", "")),
+            MetricType::Custom(custom) => custom.value,
         }
     }
 }
@@ -84,6 +87,10 @@ pub fn get_metrics_fields() -> Vec<String> {
 }
 
 pub fn get_metric_type_from_str(metric_id: &str) -> Option<MetricType> {
+    if let Some(name) = metric_id.strip_prefix("custom_") {
+        let custom = custom::CustomMetric::new(name.to_string(), 0.0);
+        return custom.validate().ok().map(|_| MetricType::Custom(custom));
+    }
     let fields = get_metrics_fields();
     if fields.contains(&metric_id.to_string()) {
         match metric_id {
@@ -107,3 +114,228 @@ pub fn get_metric_type_from_str(metric_id: &str) -> Option<MetricType> {
         None
     }
 }
+
+// Difference between the newest and the oldest sample of a `(timestamp, value)` series
+pub fn delta(history: &[(i64, f32)]) -> Option<f32> {
+    match (history.first(), history.last()) {
+        (Some(first), Some(last)) if history.len() > 1 => Some(last.1 - first.1),
+        _ => None,
+    }
+}
+
+// Average change per second between the oldest and the newest sample
+pub fn rate(history: &[(i64, f32)]) -> Option<f32> {
+    let (first, last) = (history.first()?, history.last()?);
+    let elapsed = last.0 - first.0;
+    if elapsed <= 0 {
+        return None;
+    }
+    Some((last.1 - first.1) / elapsed as f32)
+}
+
+// Least squares fit of a `(timestamp, value)` series.
+// Returns the slope per second and the fitted value at the newest sample.
+pub fn linear_regression(history: &[(i64, f32)]) -> Option<(f64, f64)> {
+    if history.len() < 2 {
+        return None;
+    }
+    // Timestamps are centered on the newest sample to keep the sums small
+    let origin = history.last()?.0;
+    let n = history.len() as f64;
+    let mean_x = history
+        .iter()
+        .map(|(t, _)| (t - origin) as f64)
+        .sum::<f64>()
+        / n;
+    let mean_y = history.iter().map(|(_, v)| *v as f64).sum::<f64>() / n;
+    let mut covariance = 0.0;
+    let mut variance = 0.0;
+    for (t, v) in history {
+        let dx = (t - origin) as f64 - mean_x;
+        covariance += dx * (*v as f64 - mean_y);
+        variance += dx * dx;
+    }
+    if variance == 0.0 {
+        return None;
+    }
+    let slope = covariance / variance;
+    Some((slope, mean_y - slope * mean_x))
+}
+
+// Hours until the fitted trend reaches `target`, `None` if it is not heading there.
+// A trend that crossed the target within the window has already reached it.
+pub fn hours_until(history: &[(i64, f32)], target: f32) -> Option<f32> {
+    let (slope, current) = linear_regression(history)?;
+    let remaining = target as f64 - current;
+    let span = (history.last()?.0 - history.first()?.0) as f64;
+    let start = current - slope * span;
+    if remaining == 0.0 || (target as f64 - start).signum() != remaining.signum() {
+        return Some(0.0);
+    }
+    if slope == 0.0 || remaining.signum() != slope.signum() {
+        return None;
+    }
+    Some((remaining / slope / 3600.0) as f32)
+}
+
+// Mean and population standard deviation of a set of samples
+pub fn mean_stddev(values: &[f32]) -> Option<(f32, f32)> {
+    if values.is_empty() {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().map(|v| *v as f64).sum::<f64>() / n;
+    let variance = values
+        .iter()
+        .map(|v| (*v as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    Some((mean as f32, variance.sqrt() as f32))
+}
+
+// Ranges an anomaly baseline is sampled from: the window right before `now`,
+// or that same window on each of the last 7 days
+pub fn baseline_ranges(now: i64, window: i64, seasonal: bool) -> Vec<(i64, i64)> {
+    if seasonal {
+        (1..=7)
+            .map(|day| (now - day * 86400 - window, now - day * 86400))
+            .collect()
+    } else {
+        vec![(now - window, now - 1)]
+    }
+}
+
+// How far `current` is from the samples, `None` when a flat or tiny baseline has no meaningful deviation
+pub fn baseline(current: f32, samples: &[f32]) -> Option<Baseline> {
+    let (mean, stddev) = mean_stddev(samples)?;
+    if samples.len() < 2 || stddev == 0.0 {
+        return None;
+    }
+    Some(Baseline {
+        mean,
+        stddev,
+        zscore: (current - mean) / stddev,
+    })
+}
+
+// AND of condition results where `None` is unknown: any `false` decides, otherwise unknown wins
+pub fn all(results: &[Option<bool>]) -> Option<bool> {
+    if results.contains(&Some(false)) {
+        return Some(false);
+    }
+    results
+        .iter()
+        .copied()
+        .collect::<Option<Vec<bool>>>()
+        .map(|_| true)
+}
+
+// OR of condition results where `None` is unknown: any `true` decides, otherwise unknown wins
+pub fn any(results: &[Option<bool>]) -> Option<bool> {
+    if results.contains(&Some(true)) {
+        return Some(true);
+    }
+    results
+        .iter()
+        .copied()
+        .collect::<Option<Vec<bool>>>()
+        .map(|_| false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hourly(values: &[f32]) -> Vec<(i64, f32)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(hour, value)| (1760000000 + hour as i64 * 3600, *value))
+            .collect()
+    }
+
+    #[test]
+    fn flat_series_never_reaches_the_target() {
+        assert_eq!(
+            linear_regression(&hourly(&[40.0, 40.0, 40.0])),
+            Some((0.0, 40.0))
+        );
+        assert_eq!(hours_until(&hourly(&[40.0, 40.0, 40.0]), 90.0), None);
+    }
+
+    #[test]
+    fn rising_series_gives_the_eta() {
+        let (slope, current) = linear_regression(&hourly(&[10.0, 20.0, 30.0])).unwrap();
+        assert!((slope * 3600.0 - 10.0).abs() < 1e-9);
+        assert!((current - 30.0).abs() < 1e-9);
+        let hours = hours_until(&hourly(&[10.0, 20.0, 30.0]), 90.0).unwrap();
+        assert!((hours - 6.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn falling_series_reaches_a_lower_target() {
+        let hours = hours_until(&hourly(&[100.0, 80.0, 60.0]), 0.0).unwrap();
+        assert!((hours - 3.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn receding_series_never_reaches_the_target() {
+        assert_eq!(hours_until(&hourly(&[60.0, 80.0, 100.0]), 0.0), None);
+    }
+
+    #[test]
+    fn target_already_exceeded() {
+        assert_eq!(
+            hours_until(&hourly(&[80.0, 90.0, 100.0, 110.0]), 95.0),
+            Some(0.0)
+        );
+        assert_eq!(hours_until(&hourly(&[20.0, 10.0, 0.0]), 0.0), Some(0.0));
+    }
+
+    #[test]
+    fn zero_variance_has_no_baseline() {
+        assert_eq!(mean_stddev(&[5.0, 5.0, 5.0]), Some((5.0, 0.0)));
+        assert!(baseline(9.0, &[5.0, 5.0, 5.0]).is_none());
+    }
+
+    #[test]
+    fn fewer_than_two_samples_have_no_baseline() {
+        assert_eq!(mean_stddev(&[]), None);
+        assert!(baseline(9.0, &[]).is_none());
+        assert!(baseline(9.0, &[5.0]).is_none());
+    }
+
+    #[test]
+    fn zscore_against_the_baseline() {
+        let baseline = baseline(10.0, &[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).unwrap();
+        assert_eq!((baseline.mean, baseline.stddev), (5.0, 2.0));
+        assert_eq!(baseline.zscore, 2.5);
+    }
+
+    #[test]
+    fn seasonal_baseline_uses_the_same_window_on_the_last_7_days() {
+        let now = 1760000000;
+        let ranges = baseline_ranges(now, 600, true);
+        assert_eq!(ranges.len(), 7);
+        assert_eq!(ranges[0], (now - 86400 - 600, now - 86400));
+        assert_eq!(ranges[6], (now - 7 * 86400 - 600, now - 7 * 86400));
+        assert_eq!(baseline_ranges(now, 600, false), vec![(now - 600, now - 1)]);
+    }
+
+    #[test]
+    fn single_sample_has_no_trend() {
+        assert_eq!(linear_regression(&hourly(&[50.0])), None);
+        assert_eq!(hours_until(&hourly(&[50.0]), 90.0), None);
+        assert_eq!(hours_until(&[], 90.0), None);
+    }
+
+    #[test]
+    fn unknown_results_only_count_when_undecided() {
+        assert_eq!(all(&[Some(true), Some(true)]), Some(true));
+        assert_eq!(all(&[Some(true), None]), None);
+        assert_eq!(all(&[None, Some(false)]), Some(false));
+        assert_eq!(any(&[Some(false), Some(false)]), Some(false));
+        assert_eq!(any(&[Some(false), None]), None);
+        assert_eq!(any(&[None, Some(true)]), Some(true));
+    }
+}
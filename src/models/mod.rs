@@ -1,7 +1,18 @@
 pub mod alert;
+pub mod backtest;
+pub mod channel;
 pub mod cpu;
+pub mod custom;
+pub mod delivery;
 pub mod disk;
+pub mod escalation;
+pub mod exec;
+pub mod history;
+pub mod incident;
+pub mod log;
 pub mod mem;
 pub mod metrics;
 pub mod os_info;
 pub mod request;
+pub mod routing;
+pub mod silence;
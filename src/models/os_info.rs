@@ -1,6 +1,7 @@
 use std::fmt::Display;
 
 // Struct for basic OS information
+#[allow(dead_code)]
 pub struct OsInfo {
     pub hostname: String,
     pub uptime: u64,
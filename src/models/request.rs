@@ -1,15 +1,24 @@
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display};
 
+use reqwest::header::HeaderName;
 use serde::{Deserialize, Serialize};
 
+use crate::utils::template;
+
 /// Example JSON:
 /// {
 ///     "request": {
-///         "type": "(GET, POST)",
+///         "type": "(GET, POST, PUT, PATCH, DELETE)",
 ///         "url": "example.com",
+///         "headers": { "X-Team": "ops" },
+///         "query": { "source": "sciigilo" },
+///         "auth": { "bearer": { "token": "secret" } },
+///         "signing": { "secret": "shared-secret" },
 ///         "body": {
-///             "format": "(application/json or x-www-urlencoded)",
-///             "payload": "Hello world!"
+///             "format": "(json, xwwwformurlencoded or text)",
+///             "content_type": "application/vnd.api+json",
+///             "payload": "Hello world!",
+///             "raw_json": true
 ///         }
 ///     }
 /// }
@@ -19,6 +28,15 @@ use serde::{Deserialize, Serialize};
 pub struct Request {
     pub request_type: RequestType,
     pub url: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub query: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Auth>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing: Option<Signing>,
+    #[serde(default)]
     pub body: Body,
 }
 
@@ -26,7 +44,14 @@ pub struct Request {
 #[serde(rename_all = "lowercase")]
 pub struct Body {
     pub format: BodyFormat,
+    // Overrides the content type implied by `format`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
     pub payload: String,
+    // `json` payloads are sent as written when set. Rules saved before this flag existed lack it
+    // and keep getting the payload encoded as one JSON string, like earlier releases sent it.
+    #[serde(default)]
+    pub raw_json: bool,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
@@ -37,6 +62,12 @@ pub enum RequestType {
     Get,
     #[serde(alias = "POST", alias = "Post")]
     Post,
+    #[serde(alias = "PUT", alias = "Put")]
+    Put,
+    #[serde(alias = "PATCH", alias = "Patch")]
+    Patch,
+    #[serde(alias = "DELETE", alias = "Delete")]
+    Delete,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
@@ -46,6 +77,22 @@ pub enum BodyFormat {
     Json,
     #[serde(alias = "X-WWW-Form-Urlencoded")]
     XWwwFormUrlEncoded,
+    #[serde(alias = "Text", alias = "text/plain")]
+    Text,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Auth {
+    Basic { username: String, password: String },
+    Bearer { token: String },
+}
+
+// HMAC-SHA256 over `{timestamp}.{body}`, sent in the `X-Sciigilo-Timestamp` and
+// `X-Sciigilo-Signature: sha256=<hex>` headers
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Signing {
+    pub secret: String,
 }
 
 impl Default for Body {
@@ -56,7 +103,42 @@ impl Default for Body {
 
 impl Body {
     pub fn new(format: BodyFormat, payload: String) -> Body {
-        Body { format, payload }
+        Body {
+            format,
+            content_type: None,
+            payload,
+            raw_json: true,
+        }
+    }
+}
+
+impl Request {
+    pub fn validate(&self) -> Result<(), String> {
+        template::validate(&self.url)?;
+        template::validate(&self.body.payload)?;
+        for (name, value) in &self.headers {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                return Err(format!("Invalid header name: {}", name));
+            }
+            template::validate(value)?;
+        }
+        for value in self.query.values() {
+            template::validate(value)?;
+        }
+        match &self.auth {
+            Some(Auth::Basic { username, password }) => {
+                template::validate(username)?;
+                template::validate(password)?;
+            }
+            Some(Auth::Bearer { token }) => template::validate(token)?,
+            None => {}
+        }
+        match &self.signing {
+            Some(signing) if signing.secret.is_empty() => {
+                Err("Signing secret must not be empty".to_string())
+            }
+            _ => Ok(()),
+        }
     }
 }
 
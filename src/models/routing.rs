@@ -0,0 +1,52 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    alert::{Alert, Severity},
+    request::Request,
+};
+
+/// One entry of the routing table. Routes are checked in order and the first one whose
+/// `severities` and `labels` match the alert notifies its `channels` (and inline `request`).
+/// Empty matchers match everything.
+///
+/// Example JSON:
+/// [
+///     { "severities": ["critical"], "channels": ["0b7d3f4e-8f5e-4a0e-9a53-3c1b1d4a8f21"] },
+///     { "severities": ["warning"], "labels": { "team": "web" }, "request": { "request_type": "post", "url": "https://chat.example.com", "body": { "format": "xwwwformurlencoded", "payload": "text={severity}: {metric}" } } }
+/// ]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct NotificationRoute {
+    #[serde(default = "uuid::Uuid::new_v4")]
+    pub id: Uuid,
+    #[serde(default)]
+    pub severities: Vec<Severity>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub channels: Vec<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Request>,
+}
+
+impl NotificationRoute {
+    pub fn matches(&self, alert: &Alert) -> bool {
+        (self.severities.is_empty() || self.severities.contains(&alert.severity))
+            && self
+                .labels
+                .iter()
+                .all(|(key, value)| alert.labels.get(key) == Some(value))
+    }
+}
+
+impl Display for NotificationRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Route {} to {} channel(s)", self.id, self.channels.len())?;
+        if let Some(request) = &self.request {
+            write!(f, " and {}", request)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::alert::Alert;
+
+/// A silence suppresses notifications for matching alerts. Without a `schedule` it is
+/// active between `starts_at` and `ends_at` (unix timestamps). With a cron `schedule`
+/// (`sec min hour day month weekday`, local time) it is a recurring maintenance window
+/// lasting `duration` seconds from every occurrence, optionally bounded by `starts_at`/`ends_at`.
+/// Empty matchers match every alert.
+///
+/// Example JSON:
+/// {
+///     "labels": { "env": "prod" },
+///     "schedule": "0 0 2 * * Sun",
+///     "duration": 7200,
+///     "created_by": "ops",
+///     "comment": "Weekly patch night"
+/// }
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Silence {
+    #[serde(default = "uuid::Uuid::new_v4")]
+    pub id: Uuid,
+    #[serde(default)]
+    pub alert_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub metric_ids: Vec<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub starts_at: Option<i64>,
+    #[serde(default)]
+    pub ends_at: Option<i64>,
+    #[serde(default)]
+    pub schedule: Option<String>,
+    #[serde(default)]
+    pub duration: Option<u64>,
+    #[serde(default)]
+    pub created_by: String,
+    #[serde(default)]
+    pub comment: String,
+}
+
+impl Silence {
+    pub fn validate(&self) -> Result<(), String> {
+        match (&self.schedule, self.duration) {
+            (Some(schedule), Some(duration)) => {
+                cron::Schedule::from_str(schedule)
+                    .map_err(|e| format!("Invalid schedule {}: {}", schedule, e))?;
+                if duration == 0 {
+                    return Err("Duration must be at least 1 second".to_string());
+                }
+            }
+            (Some(_), None) => return Err("Recurring silences need a duration".to_string()),
+            (None, _) => {
+                if self.starts_at.is_none() || self.ends_at.is_none() {
+                    return Err("Silences need starts_at and ends_at, or a schedule".to_string());
+                }
+            }
+        }
+        if let (Some(starts_at), Some(ends_at)) = (self.starts_at, self.ends_at)
+            && ends_at <= starts_at
+        {
+            return Err("ends_at must be after starts_at".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        let timestamp = now.timestamp();
+        if self
+            .starts_at
+            .is_some_and(|starts_at| timestamp < starts_at)
+            || self.ends_at.is_some_and(|ends_at| timestamp >= ends_at)
+        {
+            return false;
+        }
+        match (&self.schedule, self.duration) {
+            (Some(schedule), Some(duration)) => {
+                let Ok(schedule) = cron::Schedule::from_str(schedule) else {
+                    return false;
+                };
+                // Active if some occurrence started less than `duration` seconds ago
+                let window_start = now - chrono::Duration::seconds(duration as i64);
+                schedule
+                    .after(&window_start)
+                    .next()
+                    .is_some_and(|start| start <= now)
+            }
+            _ => true,
+        }
+    }
+
+    pub fn matches(&self, alert: &Alert) -> bool {
+        let metric_ids = alert.condition().metric_ids();
+        (self.alert_ids.is_empty() || self.alert_ids.contains(&alert.id))
+            && (self.metric_ids.is_empty()
+                || self.metric_ids.iter().any(|m| metric_ids.contains(m)))
+            && self
+                .labels
+                .iter()
+                .all(|(key, value)| alert.labels.get(key) == Some(value))
+    }
+}
+
+impl Display for Silence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "silence {} by {}", self.id, self.created_by)?;
+        if !self.comment.is_empty() {
+            write!(f, " ({})", self.comment)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    // Sunday 15 June 2025, 02:00 local time
+    fn patch_night() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2025, 6, 15, 2, 0, 0).unwrap()
+    }
+
+    fn window() -> Silence {
+        Silence {
+            schedule: Some("0 0 2 * * Sun".to_string()),
+            duration: Some(7200),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn active_inside_the_window() {
+        let start = patch_night();
+        assert!(window().is_active(start));
+        assert!(window().is_active(start + chrono::Duration::minutes(90)));
+        assert!(!window().is_active(start - chrono::Duration::seconds(1)));
+        assert!(!window().is_active(start + chrono::Duration::days(1)));
+    }
+
+    #[test]
+    fn window_ends_after_duration() {
+        let start = patch_night();
+        assert!(window().is_active(start + chrono::Duration::seconds(7199)));
+        assert!(!window().is_active(start + chrono::Duration::seconds(7200)));
+    }
+
+    #[test]
+    fn window_bounded_by_starts_at_and_ends_at() {
+        let start = patch_night();
+        let bounded = Silence {
+            ends_at: Some(start.timestamp() + 3600),
+            ..window()
+        };
+        assert!(bounded.is_active(start));
+        assert!(!bounded.is_active(start + chrono::Duration::seconds(3600)));
+    }
+
+    #[test]
+    fn one_off_silence_between_starts_at_and_ends_at() {
+        let start = patch_night();
+        let silence = Silence {
+            starts_at: Some(start.timestamp()),
+            ends_at: Some(start.timestamp() + 600),
+            ..Default::default()
+        };
+        assert!(silence.validate().is_ok());
+        assert!(!silence.is_active(start - chrono::Duration::seconds(1)));
+        assert!(silence.is_active(start));
+        assert!(silence.is_active(start + chrono::Duration::seconds(599)));
+        assert!(!silence.is_active(start + chrono::Duration::seconds(600)));
+    }
+
+    #[test]
+    fn matches_alert_ids_and_labels() {
+        let alert = Alert {
+            metric_id: "disk_usage_percentage".to_string(),
+            labels: BTreeMap::from([("env".to_string(), "prod".to_string())]),
+            ..Default::default()
+        };
+        assert!(Silence::default().matches(&alert));
+
+        let by_id = Silence {
+            alert_ids: vec![alert.id],
+            ..Default::default()
+        };
+        assert!(by_id.matches(&alert));
+        let other_id = Silence {
+            alert_ids: vec![Uuid::new_v4()],
+            ..Default::default()
+        };
+        assert!(!other_id.matches(&alert));
+
+        let by_label = |env: &str| Silence {
+            labels: BTreeMap::from([("env".to_string(), env.to_string())]),
+            ..Default::default()
+        };
+        assert!(by_label("prod").matches(&alert));
+        assert!(!by_label("staging").matches(&alert));
+
+        let by_metric = Silence {
+            metric_ids: vec!["cpu_usage_percentage".to_string()],
+            ..Default::default()
+        };
+        assert!(!by_metric.matches(&alert));
+    }
+
+    #[test]
+    fn validate_rejects_incomplete_silences() {
+        assert!(window().validate().is_ok());
+        let no_duration = Silence {
+            duration: None,
+            ..window()
+        };
+        assert!(no_duration.validate().is_err());
+        assert!(Silence::default().validate().is_err());
+    }
+}
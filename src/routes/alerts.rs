@@ -1,41 +1,59 @@
 use crate::models::metrics;
-use crate::{models::alert::Alert, utils::config::AlertConfig};
+use crate::{
+    models::alert::Alert,
+    utils::config::{AlertConfig, ConfigState},
+};
 use axum::routing::post;
 use axum::{Router, extract::State, response::Json, routing::get};
 
-async fn get_alerts(State(mut config): State<AlertConfig>) -> Json<Vec<Alert>> {
-    Json(config.get_alerts().await.to_vec())
+async fn get_alerts(State(config): State<AlertConfig>) -> Json<Vec<Alert>> {
+    Json(config.get_all().await)
 }
 
 async fn create_alert(
-    State(mut config): State<AlertConfig>,
+    State(config): State<ConfigState>,
     Json(alert_data): Json<Alert>,
 ) -> Json<Result<String, String>> {
-    config.add_alert(alert_data).await;
+    if let Err(e) = check_alert(&config, &alert_data).await {
+        return Json(Err(e));
+    }
+    config.alerts.add(alert_data).await;
     Json(Ok("Success".to_string()))
 }
 
 async fn delete_alert(
-    State(mut config): State<AlertConfig>,
+    State(config): State<AlertConfig>,
     axum::extract::Path(uuid): axum::extract::Path<String>,
 ) -> Json<Result<String, String>> {
-    config.remove_alert(&uuid).await;
+    if !config.remove(&uuid).await {
+        eprintln!("Alert with id {} not found", uuid);
+    }
     Json(Ok("Success".to_string()))
 }
 
 async fn update_alert(
-    State(mut config): State<AlertConfig>,
+    State(config): State<ConfigState>,
     Json(alert_data): Json<Alert>,
 ) -> Json<Result<String, String>> {
-    config.update_alert(alert_data).await;
+    if let Err(e) = check_alert(&config, &alert_data).await {
+        return Json(Err(e));
+    }
+    config.alerts.update(alert_data).await;
     Json(Ok("Success".to_string()))
 }
 
+async fn check_alert(config: &ConfigState, alert: &Alert) -> Result<(), String> {
+    alert.validate()?;
+    config
+        .check_references(&alert.channels, alert.escalation_policy)
+        .await
+}
+
 pub async fn get_fields(State(_config): State<AlertConfig>) -> Json<Vec<String>> {
     Json(metrics::get_metrics_fields())
 }
 
-pub fn get_routes() -> Router<AlertConfig> {
+pub fn get_routes() -> Router<ConfigState> {
     Router::new()
         .route("/get", get(get_alerts))
         .route("/create", post(create_alert))
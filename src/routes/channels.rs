@@ -0,0 +1,60 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::{get, post},
+};
+
+use crate::{
+    models::channel::Channel,
+    utils::config::{ChannelConfig, ConfigState},
+};
+
+async fn get_channels(State(config): State<ChannelConfig>) -> Json<Vec<Channel>> {
+    Json(config.get_all().await)
+}
+
+async fn create_channel(
+    State(config): State<ChannelConfig>,
+    Json(channel): Json<Channel>,
+) -> Json<Result<String, String>> {
+    if let Err(e) = channel.validate() {
+        return Json(Err(e));
+    }
+    let id = channel.id.to_string();
+    config.add(channel).await;
+    Json(Ok(id))
+}
+
+async fn delete_channel(
+    State(config): State<ConfigState>,
+    Path(uuid): Path<String>,
+) -> Json<Result<String, String>> {
+    if let Ok(id) = uuid.parse()
+        && let Some(user) = config.channel_users(id).await
+    {
+        return Json(Err(format!("Channel {} is still used by {}", uuid, user)));
+    }
+    if !config.channels.remove(&uuid).await {
+        return Json(Err(format!("Channel with id {} not found", uuid)));
+    }
+    Json(Ok("Success".to_string()))
+}
+
+async fn update_channel(
+    State(config): State<ChannelConfig>,
+    Json(channel): Json<Channel>,
+) -> Json<Result<String, String>> {
+    if let Err(e) = channel.validate() {
+        return Json(Err(e));
+    }
+    config.update(channel).await;
+    Json(Ok("Success".to_string()))
+}
+
+pub fn get_routes() -> Router<ConfigState> {
+    Router::new()
+        .route("/get", get(get_channels))
+        .route("/create", post(create_channel))
+        .route("/delete/{uuid}", get(delete_channel))
+        .route("/update", post(update_channel))
+}
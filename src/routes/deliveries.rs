@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    routing::get,
+};
+use sqlx::SqlitePool;
+
+use crate::{models::delivery::Delivery, utils::db};
+
+#[derive(serde::Deserialize)]
+struct DeliveriesQuery {
+    n: Option<u32>,
+}
+
+async fn get_deliveries(
+    State(pool): State<Arc<SqlitePool>>,
+    query: Query<DeliveriesQuery>,
+) -> Json<Result<Vec<Delivery>, String>> {
+    match db::get_deliveries(&pool, query.n.unwrap_or(50), false).await {
+        Ok(deliveries) => Json(Ok(deliveries)),
+        Err(e) => Json(Err(format!("Failed to read deliveries: {:?}", e))),
+    }
+}
+
+async fn get_failed_deliveries(
+    State(pool): State<Arc<SqlitePool>>,
+    query: Query<DeliveriesQuery>,
+) -> Json<Result<Vec<Delivery>, String>> {
+    match db::get_deliveries(&pool, query.n.unwrap_or(50), true).await {
+        Ok(deliveries) => Json(Ok(deliveries)),
+        Err(e) => Json(Err(format!("Failed to read deliveries: {:?}", e))),
+    }
+}
+
+pub fn get_routes() -> Router<Arc<SqlitePool>> {
+    Router::new()
+        .route("/get", get(get_deliveries))
+        .route("/failed", get(get_failed_deliveries))
+}
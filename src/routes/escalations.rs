@@ -0,0 +1,71 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::{get, post},
+};
+
+use crate::{
+    models::escalation::EscalationPolicy,
+    utils::config::{ConfigState, EscalationConfig},
+};
+
+async fn get_policies(State(config): State<EscalationConfig>) -> Json<Vec<EscalationPolicy>> {
+    Json(config.get_all().await)
+}
+
+async fn create_policy(
+    State(config): State<ConfigState>,
+    Json(policy): Json<EscalationPolicy>,
+) -> Json<Result<String, String>> {
+    if let Err(e) = check_policy(&config, &policy).await {
+        return Json(Err(e));
+    }
+    let id = policy.id.to_string();
+    config.escalations.add(policy).await;
+    Json(Ok(id))
+}
+
+async fn delete_policy(
+    State(config): State<ConfigState>,
+    Path(uuid): Path<String>,
+) -> Json<Result<String, String>> {
+    if let Ok(id) = uuid.parse()
+        && let Some(user) = config.policy_users(id).await
+    {
+        return Json(Err(format!(
+            "Escalation policy {} is still used by {}",
+            uuid, user
+        )));
+    }
+    if !config.escalations.remove(&uuid).await {
+        return Json(Err(format!("Escalation policy with id {} not found", uuid)));
+    }
+    Json(Ok("Success".to_string()))
+}
+
+async fn update_policy(
+    State(config): State<ConfigState>,
+    Json(policy): Json<EscalationPolicy>,
+) -> Json<Result<String, String>> {
+    if let Err(e) = check_policy(&config, &policy).await {
+        return Json(Err(e));
+    }
+    config.escalations.update(policy).await;
+    Json(Ok("Success".to_string()))
+}
+
+async fn check_policy(config: &ConfigState, policy: &EscalationPolicy) -> Result<(), String> {
+    policy.validate()?;
+    for step in &policy.steps {
+        config.check_references(&step.channels, None).await?;
+    }
+    Ok(())
+}
+
+pub fn get_routes() -> Router<ConfigState> {
+    Router::new()
+        .route("/get", get(get_policies))
+        .route("/create", post(create_policy))
+        .route("/delete/{uuid}", get(delete_policy))
+        .route("/update", post(update_policy))
+}
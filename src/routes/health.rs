@@ -0,0 +1,133 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, LazyLock},
+};
+
+use axum::{Json, Router, extract::State, http::StatusCode, routing::get};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use sysinfo::System;
+
+use crate::models::metrics::MetricType;
+use crate::utils::{collector::STARTED_AT, db};
+
+// The collector writes every second, anything older means it stopped or hangs
+const COLLECTOR_STALE_AFTER: i64 = 30;
+// Empty tables right after the first start are not an error yet
+const STARTUP_GRACE: i64 = 60;
+
+#[derive(Serialize)]
+struct Health {
+    status: String,
+    version: String,
+    hostname: String,
+    uptime: u64,
+    collector: CollectorHealth,
+    // Seconds since the newest value of each custom metric. Push intervals are up to the
+    // external jobs, so these never make the service stale, use absence conditions instead.
+    custom_last_sample_age: BTreeMap<String, i64>,
+}
+
+#[derive(Serialize)]
+struct CollectorHealth {
+    stale: bool,
+    // Seconds since the newest row of each metric table, `None` if the table is empty
+    last_sample_age: BTreeMap<String, Option<i64>>,
+}
+
+pub fn get_routes() -> Router<Arc<SqlitePool>> {
+    LazyLock::force(&STARTED_AT);
+    Router::new().route("/health", get(health))
+}
+
+async fn health(State(pool): State<Arc<SqlitePool>>) -> (StatusCode, Json<Health>) {
+    let now = chrono::Utc::now().timestamp();
+    let mut last_sample_age = BTreeMap::new();
+    for (name, metric_type) in [
+        ("cpu", MetricType::Cpu(Default::default())),
+        ("memory", MetricType::Memory(Default::default())),
+        ("disk", MetricType::Disk(Default::default())),
+    ] {
+        let age = db::get_last_timestamp(&pool, &metric_type)
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to read last {} sample: {:?}", name, e);
+                None
+            })
+            .map(|last| now - last);
+        last_sample_age.insert(name.to_string(), age);
+    }
+    let custom_last_sample_age = db::get_custom_last_timestamps(&pool)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to read last custom samples: {:?}", e);
+            Vec::new()
+        })
+        .into_iter()
+        .map(|(name, last)| (name, now - last))
+        .collect();
+
+    let status = collector_status(&last_sample_age, now - *STARTED_AT);
+    let stale = status == "stale";
+    let health = Health {
+        status: status.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        hostname: System::host_name().unwrap_or("unknown".to_string()),
+        uptime: System::uptime(),
+        collector: CollectorHealth {
+            stale,
+            last_sample_age,
+        },
+        custom_last_sample_age,
+    };
+    let status = if stale {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(health))
+}
+
+// "starting" while a table has no row yet within the grace period after startup
+fn collector_status(last_sample_age: &BTreeMap<String, Option<i64>>, running_for: i64) -> &str {
+    let mut status = "ok";
+    for age in last_sample_age.values() {
+        match age {
+            Some(age) if *age > COLLECTOR_STALE_AFTER => return "stale",
+            None if running_for > STARTUP_GRACE => return "stale",
+            None => status = "starting",
+            Some(_) => {}
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ages(cpu: Option<i64>, disk: Option<i64>) -> BTreeMap<String, Option<i64>> {
+        BTreeMap::from([("cpu".to_string(), cpu), ("disk".to_string(), disk)])
+    }
+
+    #[test]
+    fn empty_tables_are_fine_during_startup() {
+        assert_eq!(collector_status(&ages(Some(1), Some(2)), 5), "ok");
+        assert_eq!(collector_status(&ages(Some(1), None), 5), "starting");
+        assert_eq!(
+            collector_status(&ages(None, None), STARTUP_GRACE),
+            "starting"
+        );
+        assert_eq!(
+            collector_status(&ages(Some(1), None), STARTUP_GRACE + 1),
+            "stale"
+        );
+    }
+
+    #[test]
+    fn old_samples_are_stale() {
+        assert_eq!(collector_status(&ages(Some(1), Some(31)), 5), "stale");
+        assert_eq!(collector_status(&ages(None, Some(31)), 5), "stale");
+        assert_eq!(collector_status(&ages(Some(30), Some(30)), 600), "ok");
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    routing::{get, post},
+};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        backtest::{Backtest, BacktestRequest},
+        history::{AlertHistory, HistoryFilter},
+        incident::AlertState,
+    },
+    utils::{backtest, db},
+};
+
+#[derive(serde::Deserialize)]
+struct HistoryQuery {
+    alert_id: Option<Uuid>,
+    metric_id: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+    state: Option<AlertState>,
+    limit: Option<u32>,
+}
+
+// `/alerts/history?alert_id=...&metric_id=cpu_usage_percentage&since=1760000000&state=firing&limit=100`
+async fn get_history(
+    State(pool): State<Arc<SqlitePool>>,
+    Query(query): Query<HistoryQuery>,
+) -> Json<Result<AlertHistory, String>> {
+    let filter = HistoryFilter {
+        alert_id: query.alert_id,
+        metric_id: query.metric_id,
+        since: query.since,
+        until: query.until,
+        state: query.state,
+    };
+    let limit = query.limit.unwrap_or(100).min(1000);
+    let history = async {
+        Ok::<_, sqlx::Error>(AlertHistory {
+            events: db::get_alert_events(&pool, &filter, limit).await?,
+            daily: db::count_firings_per_day(&pool, &filter).await?,
+            time_to_resolve: db::mean_time_to_resolve(&pool, &filter).await?,
+        })
+    };
+    match history.await {
+        Ok(history) => Json(Ok(history)),
+        Err(e) => Json(Err(format!("Failed to read alert history: {:?}", e))),
+    }
+}
+
+// Would-be firings and resolutions of a rule that does not have to be saved yet
+async fn backtest_alert(
+    State(pool): State<Arc<SqlitePool>>,
+    Json(request): Json<BacktestRequest>,
+) -> Json<Result<Backtest, String>> {
+    Json(backtest::run(&pool, &request).await)
+}
+
+pub fn get_routes() -> Router<Arc<SqlitePool>> {
+    Router::new()
+        .route("/alerts/history", get(get_history))
+        .route("/alerts/backtest", post(backtest_alert))
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{Path, Query, State},
+    routing::{get, post},
+};
+use serde::Deserialize;
+use sqlx::SqlitePool;
+
+use crate::{
+    models::incident::{EventKind, Incident, IncidentEvent},
+    utils::{config::ConfigState, db, log::Logger, notifier::Notifier, watchtower},
+};
+
+#[derive(Clone)]
+pub struct IncidentState {
+    pub pool: Arc<SqlitePool>,
+    pub config: ConfigState,
+    pub logger: Logger,
+    pub notifier: Notifier,
+}
+
+#[derive(Deserialize, Default)]
+struct Acknowledgement {
+    acked_by: Option<String>,
+    note: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IncidentsQuery {
+    n: Option<u32>,
+    #[serde(default)]
+    open: bool,
+}
+
+async fn acknowledge_alert(
+    State(mut state): State<IncidentState>,
+    Path(uuid): Path<String>,
+    body: Bytes,
+) -> Json<Result<String, String>> {
+    // The body is optional, a bare POST acknowledges anonymously
+    let ack: Acknowledgement = match body.is_empty() {
+        true => Acknowledgement::default(),
+        false => match serde_json::from_slice(&body) {
+            Ok(ack) => ack,
+            Err(e) => return Json(Err(format!("Invalid acknowledgement: {}", e))),
+        },
+    };
+    let now = chrono::Utc::now().timestamp();
+    let incident = match db::acknowledge_incident(
+        &state.pool,
+        &uuid,
+        now,
+        ack.acked_by.as_deref(),
+        ack.note.as_deref(),
+    )
+    .await
+    {
+        Ok(Some(incident)) => incident,
+        Ok(None) => {
+            return Json(Err(format!(
+                "Alert {} has no unacknowledged incident",
+                uuid
+            )));
+        }
+        Err(e) => return Json(Err(format!("Failed to acknowledge alert: {:?}", e))),
+    };
+
+    let detail = match (&ack.acked_by, &ack.note) {
+        (Some(by), Some(note)) => format!("by {}: {}", by, note),
+        (Some(by), None) => format!("by {}", by),
+        (None, Some(note)) => note.clone(),
+        (None, None) => String::new(),
+    };
+    let event = IncidentEvent::new(EventKind::Acknowledged, detail);
+    if let Err(e) = db::add_incident_event(&state.pool, incident.id, &event).await {
+        eprintln!("Failed to record acknowledgement: {:?}", e);
+    }
+
+    let Some(alert) = state
+        .config
+        .alerts
+        .get_all()
+        .await
+        .into_iter()
+        .find(|a| a.id == incident.alert_id)
+    else {
+        return Json(Ok(incident.id.to_string()));
+    };
+    if let Err(e) = state.logger.log_event("Acknowledged", &alert, &[]) {
+        eprintln!("Failed to log acknowledgement: {:?}", e);
+    }
+
+    // Incident services that were already notified acknowledge their side as well
+    let channels = state.config.channels.get_all().await;
+    let routes = state.config.routing.get_all().await;
+    let policies = state.config.escalations.get_all().await;
+    let route = routes.iter().find(|r| r.matches(&alert));
+    let policy = alert
+        .escalation_policy
+        .and_then(|id| policies.iter().find(|p| p.id == id));
+    for target in watchtower::notified_targets(&alert, &incident, policy, route, &channels)
+        .iter()
+        .filter(|t| t.sends_acknowledged())
+    {
+        state.notifier.acknowledge(target, &alert);
+    }
+    Json(Ok(incident.id.to_string()))
+}
+
+async fn get_incidents(
+    State(state): State<IncidentState>,
+    query: Query<IncidentsQuery>,
+) -> Json<Result<Vec<Incident>, String>> {
+    let mut incidents =
+        match db::get_incidents(&state.pool, query.n.unwrap_or(25), query.open).await {
+            Ok(incidents) => incidents,
+            Err(e) => return Json(Err(format!("Failed to read incidents: {:?}", e))),
+        };
+    for incident in incidents.iter_mut() {
+        match db::get_incident_events(&state.pool, incident.id).await {
+            Ok(events) => incident.events = events,
+            Err(e) => return Json(Err(format!("Failed to read incident timeline: {:?}", e))),
+        }
+    }
+    Json(Ok(incidents))
+}
+
+async fn get_incident(
+    State(state): State<IncidentState>,
+    Path(uuid): Path<String>,
+) -> Json<Result<Incident, String>> {
+    let mut incident = match db::get_incident(&state.pool, &uuid).await {
+        Ok(Some(incident)) => incident,
+        Ok(None) => return Json(Err(format!("Incident {} not found", uuid))),
+        Err(e) => return Json(Err(format!("Failed to read incident: {:?}", e))),
+    };
+    match db::get_incident_events(&state.pool, incident.id).await {
+        Ok(events) => incident.events = events,
+        Err(e) => return Json(Err(format!("Failed to read incident timeline: {:?}", e))),
+    }
+    Json(Ok(incident))
+}
+
+// Mounted at the root since acknowledgements live under `/alerts/{id}`
+pub fn get_routes() -> Router<IncidentState> {
+    Router::new()
+        .route("/alerts/{uuid}/ack", post(acknowledge_alert))
+        .route("/incidents/get", get(get_incidents))
+        .route("/incidents/{uuid}", get(get_incident))
+}
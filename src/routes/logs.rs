@@ -3,20 +3,59 @@ use axum::{
     extract::{Query, State},
 };
 
-use crate::utils::log::Logger;
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        incident::AlertState,
+        log::{LogEntry, LogFilter},
+    },
+    utils::log::Logger,
+};
 
 #[derive(serde::Deserialize)]
 struct LogsQuery {
     n: Option<usize>,
 }
 
+#[derive(serde::Deserialize)]
+struct EntriesQuery {
+    since: Option<i64>,
+    until: Option<i64>,
+    alert_id: Option<Uuid>,
+    state: Option<AlertState>,
+    event: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
 async fn get_logs(State(logger): State<Logger>, query: Query<LogsQuery>) -> Json<Vec<String>> {
     let n = query.n.unwrap_or(0);
     let logs = logger.get(n).unwrap();
-    logs.join("\n");
     Json(logs)
 }
 
+// `/logs/query?since=1760000000&alert_id=...&state=firing&event=notified&offset=0&limit=100`
+async fn query_logs(
+    State(logger): State<Logger>,
+    Query(query): Query<EntriesQuery>,
+) -> Json<Result<Vec<LogEntry>, String>> {
+    let filter = LogFilter {
+        since: query.since,
+        until: query.until,
+        alert_id: query.alert_id,
+        state: query.state,
+        event: query.event,
+    };
+    let limit = query.limit.unwrap_or(100).min(1000);
+    match logger.query(&filter, query.offset.unwrap_or(0), limit) {
+        Ok(entries) => Json(Ok(entries)),
+        Err(e) => Json(Err(format!("Failed to read logs: {:?}", e))),
+    }
+}
+
 pub fn get_routes() -> axum::Router<Logger> {
-    axum::Router::new().route("/get", axum::routing::get(get_logs))
+    axum::Router::new()
+        .route("/get", axum::routing::get(get_logs))
+        .route("/query", axum::routing::get(query_logs))
 }
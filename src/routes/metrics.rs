@@ -1,9 +1,16 @@
 use std::sync::Arc;
 
+use crate::models::custom::CustomMetric;
+use crate::models::disk::DiskForecast;
 use crate::models::metrics::MetricType;
 use crate::utils::db;
-use axum::extract::Query;
-use axum::{Router, extract::State, response::Json, routing::get};
+use axum::extract::{Path, Query};
+use axum::{
+    Router,
+    extract::State,
+    response::Json,
+    routing::{get, post},
+};
 use sqlx::SqlitePool;
 
 #[derive(serde::Deserialize)]
@@ -12,6 +19,14 @@ struct MetricHistory {
     end_time: Option<i64>,
 }
 
+#[derive(serde::Deserialize)]
+struct ForecastParams {
+    window: Option<u64>,
+}
+
+// Six hours of history by default
+const DEFAULT_FORECAST_WINDOW: u64 = 21600;
+
 pub fn get_routes() -> Router<Arc<SqlitePool>> {
     Router::new()
         .route("/cpu", get(get_last_cpu_metrics))
@@ -19,21 +34,27 @@ pub fn get_routes() -> Router<Arc<SqlitePool>> {
         .route("/memory", get(get_last_memory_metrics))
         .route("/memory/history", get(memory_history))
         .route("/disk", get(get_last_disk_metrics))
+        .route("/disk/forecast", get(disk_forecast))
+        .route("/custom", post(push_custom_metric))
+        .route("/custom/{name}", get(get_last_custom_metric))
 }
 
 async fn get_last_cpu_metrics(State(pool): State<Arc<SqlitePool>>) -> Json<MetricType> {
-    let metric = db::get_metric(&pool, MetricType::Cpu(Default::default())).await;
-    Json(metric)
+    let default = MetricType::Cpu(Default::default());
+    let metric = db::get_metric(&pool, default.clone()).await;
+    Json(metric.unwrap_or(default))
 }
 
 async fn get_last_memory_metrics(State(pool): State<Arc<SqlitePool>>) -> Json<MetricType> {
-    let metric = db::get_metric(&pool, MetricType::Memory(Default::default())).await;
-    Json(metric)
+    let default = MetricType::Memory(Default::default());
+    let metric = db::get_metric(&pool, default.clone()).await;
+    Json(metric.unwrap_or(default))
 }
 
 async fn get_last_disk_metrics(State(pool): State<Arc<SqlitePool>>) -> Json<MetricType> {
-    let metric = db::get_metric(&pool, MetricType::Disk(Default::default())).await;
-    Json(metric)
+    let default = MetricType::Disk(Default::default());
+    let metric = db::get_metric(&pool, default.clone()).await;
+    Json(metric.unwrap_or(default))
 }
 
 async fn cpu_history(
@@ -59,3 +80,44 @@ async fn memory_history(
         .unwrap_or(vec![]);
     Json(rows)
 }
+
+async fn disk_forecast(
+    State(pool): State<Arc<SqlitePool>>,
+    params: Query<ForecastParams>,
+) -> Json<Vec<DiskForecast>> {
+    let window = params.window.unwrap_or(DEFAULT_FORECAST_WINDOW);
+    let since = chrono::Utc::now().timestamp() - window as i64;
+    let mount_points = db::get_mount_points(&pool, since).await.unwrap_or(vec![]);
+    let mut forecasts = Vec::with_capacity(mount_points.len());
+    for mount_point in mount_points {
+        let history = db::get_mount_history(&pool, &mount_point, since)
+            .await
+            .unwrap_or(vec![]);
+        if let Some(forecast) = DiskForecast::from_history(mount_point, &history) {
+            forecasts.push(forecast);
+        }
+    }
+    Json(forecasts)
+}
+
+async fn push_custom_metric(
+    State(pool): State<Arc<SqlitePool>>,
+    Json(metric): Json<CustomMetric>,
+) -> Json<Result<String, String>> {
+    if let Err(e) = metric.validate() {
+        return Json(Err(e));
+    }
+    match db::insert_metrics(&pool, MetricType::Custom(metric)).await {
+        Ok(_) => Json(Ok("Success".to_string())),
+        Err(e) => Json(Err(format!("Failed to insert custom metric: {:?}", e))),
+    }
+}
+
+async fn get_last_custom_metric(
+    State(pool): State<Arc<SqlitePool>>,
+    Path(name): Path<String>,
+) -> Json<MetricType> {
+    let default = MetricType::Custom(CustomMetric::new(name, 0.0));
+    let metric = db::get_metric(&pool, default.clone()).await;
+    Json(metric.unwrap_or(default))
+}
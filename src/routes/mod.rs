@@ -1,4 +1,12 @@
 pub mod alerts;
+pub mod channels;
+pub mod deliveries;
+pub mod escalations;
+pub mod health;
+pub mod history;
+pub mod incidents;
 pub mod index;
 pub mod logs;
 pub mod metrics;
+pub mod routing;
+pub mod silences;
@@ -0,0 +1,36 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    routing::{get, post},
+};
+
+use crate::{
+    models::routing::NotificationRoute,
+    utils::config::{ConfigState, RoutingConfig},
+};
+
+async fn get_routing(State(config): State<RoutingConfig>) -> Json<Vec<NotificationRoute>> {
+    Json(config.get_all().await)
+}
+
+async fn update_routing(
+    State(config): State<ConfigState>,
+    Json(routes): Json<Vec<NotificationRoute>>,
+) -> Json<Result<String, String>> {
+    for route in &routes {
+        if let Some(Err(e)) = route.request.as_ref().map(|request| request.validate()) {
+            return Json(Err(e));
+        }
+        if let Err(e) = config.check_references(&route.channels, None).await {
+            return Json(Err(e));
+        }
+    }
+    config.routing.set_all(routes).await;
+    Json(Ok("Success".to_string()))
+}
+
+pub fn get_routes() -> Router<ConfigState> {
+    Router::new()
+        .route("/get", get(get_routing))
+        .route("/update", post(update_routing))
+}
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::{get, post},
+};
+use sqlx::SqlitePool;
+
+use crate::models::silence::Silence;
+use crate::utils::db;
+
+async fn get_silences(State(pool): State<Arc<SqlitePool>>) -> Json<Vec<Silence>> {
+    Json(db::get_silences(&pool).await.unwrap_or(vec![]))
+}
+
+async fn get_active_silences(State(pool): State<Arc<SqlitePool>>) -> Json<Vec<Silence>> {
+    let now = chrono::Local::now();
+    let silences = db::get_silences(&pool).await.unwrap_or(vec![]);
+    Json(silences.into_iter().filter(|s| s.is_active(now)).collect())
+}
+
+async fn save_silence(
+    State(pool): State<Arc<SqlitePool>>,
+    Json(silence): Json<Silence>,
+) -> Json<Result<String, String>> {
+    if let Err(e) = silence.validate() {
+        return Json(Err(e));
+    }
+    match db::save_silence(&pool, &silence).await {
+        Ok(_) => Json(Ok(silence.id.to_string())),
+        Err(e) => Json(Err(format!("Failed to save silence: {:?}", e))),
+    }
+}
+
+async fn delete_silence(
+    State(pool): State<Arc<SqlitePool>>,
+    Path(uuid): Path<String>,
+) -> Json<Result<String, String>> {
+    match db::delete_silence(&pool, &uuid).await {
+        Ok(true) => Json(Ok("Success".to_string())),
+        Ok(false) => Json(Err(format!("Silence with id {} not found", uuid))),
+        Err(e) => Json(Err(format!("Failed to delete silence: {:?}", e))),
+    }
+}
+
+pub fn get_routes() -> Router<Arc<SqlitePool>> {
+    Router::new()
+        .route("/get", get(get_silences))
+        .route("/active", get(get_active_silences))
+        .route("/create", post(save_silence))
+        .route("/update", post(save_silence))
+        .route("/delete/{uuid}", get(delete_silence))
+}
@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+
+use sqlx::SqlitePool;
+
+use crate::models::{
+    alert::{Anomaly, Change, Condition, Observation},
+    backtest::{Backtest, BacktestEvent, BacktestRequest},
+    incident::AlertState,
+    metrics::{self, MetricType},
+};
+
+use super::db;
+
+// The sleep between two watchtower ticks
+const DEFAULT_STEP: u64 = 5;
+// A week at the default step still fits
+const MAX_EVALUATIONS: i64 = 200_000;
+// Loaded before every window so the first evaluations see the value current at that time
+const LOOKBACK: i64 = 3600;
+
+// Replays a rule over the stored metrics the way the watchtower evaluates it: it fires on the
+// first matching evaluation and resolves on the first one that does not match. Silences,
+// acknowledgements and notifications are left out.
+pub async fn run(pool: &SqlitePool, request: &BacktestRequest) -> Result<Backtest, String> {
+    request.alert.validate()?;
+    if request.until <= request.since {
+        return Err("until must be after since".to_string());
+    }
+    let step = request.step.unwrap_or(DEFAULT_STEP).max(1);
+    let evaluations = (request.until - request.since) / step as i64 + 1;
+    if evaluations > MAX_EVALUATIONS {
+        return Err(format!(
+            "{} evaluations exceed the limit of {}, use a larger step or a shorter range",
+            evaluations, MAX_EVALUATIONS
+        ));
+    }
+    let condition = request.alert.condition();
+    let replay = Replay::load(pool, &condition, request.since, request.until)
+        .await
+        .map_err(|e| format!("Failed to read metric history: {:?}", e))?;
+    Ok(replay.run(&condition, request.since, request.until, step))
+}
+
+// Every series a condition reads, loaded once for the whole range, oldest sample first
+struct Replay {
+    series: HashMap<String, Vec<(i64, f32)>>,
+    // First replayed tick, absence is counted from here like from a process start
+    start: i64,
+}
+
+impl Replay {
+    async fn load(
+        pool: &SqlitePool,
+        condition: &Condition,
+        since: i64,
+        until: i64,
+    ) -> Result<Replay, sqlx::Error> {
+        let mut lookbacks = HashMap::new();
+        collect_lookbacks(condition, &mut lookbacks);
+        let mut series = HashMap::new();
+        for ((metric_id, mount_point), lookback) in lookbacks {
+            let from = since - lookback;
+            let history = match &mount_point {
+                Some(mount_point) => db::get_mount_history(pool, mount_point, from)
+                    .await?
+                    .into_iter()
+                    .filter(|(t, _)| *t <= until)
+                    .map(|(t, m)| (t, MetricType::Disk(m).get_value(metric_id.clone())))
+                    .collect(),
+                None => {
+                    let Some(metric_type) = metrics::get_metric_type_from_str(&metric_id) else {
+                        continue;
+                    };
+                    db::get_metric_history(pool, metric_type, &metric_id, from, until).await?
+                }
+            };
+            series.insert(series_key(&metric_id, mount_point.as_deref()), history);
+        }
+        Ok(Replay {
+            series,
+            start: since,
+        })
+    }
+
+    // Samples with `since <= t <= until`
+    fn range(&self, key: &str, since: i64, until: i64) -> &[(i64, f32)] {
+        let Some(series) = self.series.get(key) else {
+            return &[];
+        };
+        let start = series.partition_point(|(t, _)| *t < since);
+        let end = series.partition_point(|(t, _)| *t <= until);
+        &series[start..end.max(start)]
+    }
+
+    // Ticks before the first loaded sample of a metric have no value to compare
+    fn latest(&self, key: &str, now: i64) -> Option<(i64, f32)> {
+        self.range(key, i64::MIN, now).last().copied()
+    }
+
+    fn run(&self, condition: &Condition, since: i64, until: i64, step: u64) -> Backtest {
+        let mut backtest = Backtest::default();
+        let mut started = None;
+        for now in (since..=until).step_by(step as usize) {
+            let mut observations = Vec::new();
+            backtest.evaluations += 1;
+            // Undecided evaluations keep the state, like a gap in the data does in the watchtower
+            let Some(firing) = self.evaluate(condition, now, &mut observations) else {
+                continue;
+            };
+            let state = match (firing, started) {
+                (true, None) => {
+                    started = Some(now);
+                    backtest.firings += 1;
+                    AlertState::Firing
+                }
+                (false, Some(start)) => {
+                    started = None;
+                    backtest.firing_seconds += now - start;
+                    AlertState::Resolved
+                }
+                _ => continue,
+            };
+            backtest.events.push(BacktestEvent {
+                state,
+                timestamp: now,
+                observations,
+            });
+        }
+        backtest
+    }
+
+    // Same rules as the watchtower, evaluated at `now` instead of the current time
+    fn evaluate(
+        &self,
+        condition: &Condition,
+        now: i64,
+        observations: &mut Vec<Observation>,
+    ) -> Option<bool> {
+        match condition {
+            Condition::Threshold(threshold) => {
+                let (Some((_, value)), Ok(limit)) = (
+                    self.latest(&threshold.metric_id, now),
+                    threshold.value.parse::<f32>(),
+                ) else {
+                    return None;
+                };
+                observations.push(Observation {
+                    metric_id: threshold.metric_id.clone(),
+                    value,
+                    baseline: None,
+                });
+                Some(threshold.logic.check(value, limit))
+            }
+            Condition::Rate(change) => self.check_change(change, metrics::rate, now, observations),
+            Condition::Delta(change) => {
+                self.check_change(change, metrics::delta, now, observations)
+            }
+            Condition::Forecast(forecast) => {
+                let (Ok(target), Ok(value)) = (
+                    forecast.target.parse::<f32>(),
+                    forecast.value.parse::<f32>(),
+                ) else {
+                    return None;
+                };
+                let key = series_key(&forecast.metric_id, forecast.mount_point.as_deref());
+                let history = self.range(&key, now - forecast.window as i64, now);
+                if history.len() < 2 {
+                    return None;
+                }
+                let Some(hours) = metrics::hours_until(history, target) else {
+                    return Some(false);
+                };
+                observations.push(Observation {
+                    metric_id: forecast.metric_id.clone(),
+                    value: hours,
+                    baseline: None,
+                });
+                Some(forecast.logic.check(hours, value))
+            }
+            Condition::Anomaly(anomaly) => self.check_anomaly(anomaly, now, observations),
+            Condition::Absence(absence) => {
+                // Silence before the replayed range is not counted, like before the process start
+                let last = self.latest(&absence.metric_id, now).map_or(0, |(t, _)| t);
+                let age = now - last.max(self.start);
+                observations.push(Observation {
+                    metric_id: absence.metric_id.clone(),
+                    value: age as f32,
+                    baseline: None,
+                });
+                Some(age > absence.window as i64)
+            }
+            Condition::And(conditions) => {
+                let results: Vec<Option<bool>> = conditions
+                    .iter()
+                    .map(|condition| self.evaluate(condition, now, observations))
+                    .collect();
+                metrics::all(&results)
+            }
+            Condition::Or(conditions) => {
+                let results: Vec<Option<bool>> = conditions
+                    .iter()
+                    .map(|condition| self.evaluate(condition, now, observations))
+                    .collect();
+                metrics::any(&results)
+            }
+            Condition::Not(condition) => self
+                .evaluate(condition, now, observations)
+                .map(|result| !result),
+        }
+    }
+
+    fn check_change(
+        &self,
+        change: &Change,
+        compute: fn(&[(i64, f32)]) -> Option<f32>,
+        now: i64,
+        observations: &mut Vec<Observation>,
+    ) -> Option<bool> {
+        let value = change.value.parse::<f32>().ok()?;
+        let history = self.range(&change.metric_id, now - change.window as i64, now);
+        let observed = compute(history)?;
+        observations.push(Observation {
+            metric_id: change.metric_id.clone(),
+            value: observed,
+            baseline: None,
+        });
+        Some(change.logic.check(observed, value))
+    }
+
+    fn check_anomaly(
+        &self,
+        anomaly: &Anomaly,
+        now: i64,
+        observations: &mut Vec<Observation>,
+    ) -> Option<bool> {
+        let (_, current) = self.latest(&anomaly.metric_id, now)?;
+        let samples: Vec<f32> =
+            metrics::baseline_ranges(now, anomaly.window as i64, anomaly.seasonal)
+                .iter()
+                .flat_map(|(since, until)| self.range(&anomaly.metric_id, *since, *until))
+                .map(|(_, v)| *v)
+                .collect();
+        if samples.len() < 2 {
+            return None;
+        }
+        let Some(baseline) = metrics::baseline(current, &samples) else {
+            return Some(false);
+        };
+        let zscore = baseline.zscore;
+        observations.push(Observation {
+            metric_id: anomaly.metric_id.clone(),
+            value: current,
+            baseline: Some(baseline),
+        });
+        Some(zscore.abs() > anomaly.sigma)
+    }
+}
+
+// Seconds of history needed before `since` per metric and mount point
+fn collect_lookbacks(
+    condition: &Condition,
+    lookbacks: &mut HashMap<(String, Option<String>), i64>,
+) {
+    let (metric_id, mount_point, window) = match condition {
+        Condition::Threshold(threshold) => (&threshold.metric_id, None, 0),
+        Condition::Rate(change) | Condition::Delta(change) => {
+            (&change.metric_id, None, change.window as i64)
+        }
+        Condition::Forecast(forecast) => (
+            &forecast.metric_id,
+            forecast.mount_point.clone(),
+            forecast.window as i64,
+        ),
+        Condition::Anomaly(anomaly) if anomaly.seasonal => {
+            (&anomaly.metric_id, None, 7 * 86400 + anomaly.window as i64)
+        }
+        Condition::Anomaly(anomaly) => (&anomaly.metric_id, None, anomaly.window as i64),
+        Condition::Absence(absence) => (&absence.metric_id, None, absence.window as i64),
+        Condition::And(conditions) | Condition::Or(conditions) => {
+            for condition in conditions {
+                collect_lookbacks(condition, lookbacks);
+            }
+            return;
+        }
+        Condition::Not(condition) => return collect_lookbacks(condition, lookbacks),
+    };
+    let lookback = lookbacks
+        .entry((metric_id.clone(), mount_point))
+        .or_default();
+    *lookback = (*lookback).max(window + LOOKBACK);
+}
+
+// Mount point forecasts read their own series
+fn series_key(metric_id: &str, mount_point: Option<&str>) -> String {
+    match mount_point {
+        Some(mount_point) => format!("{}@{}", metric_id, mount_point),
+        None => metric_id.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::alert::{Absence, Logic, Threshold},
+        utils::notifier::tests::alert,
+    };
+
+    fn replay(series: &[(&str, &[(i64, f32)])]) -> Replay {
+        Replay {
+            series: series
+                .iter()
+                .map(|(key, samples)| (key.to_string(), samples.to_vec()))
+                .collect(),
+            start: 0,
+        }
+    }
+
+    fn transitions(backtest: &Backtest) -> Vec<(AlertState, i64)> {
+        backtest
+            .events
+            .iter()
+            .map(|event| (event.state, event.timestamp))
+            .collect()
+    }
+
+    #[test]
+    fn fires_and_resolves_on_the_first_changed_evaluation() {
+        let replay = replay(&[(
+            "cpu_usage_percentage",
+            &[(0, 50.0), (10, 95.0), (20, 96.0), (30, 40.0), (50, 99.0)],
+        )]);
+        let condition = Condition::Threshold(Threshold {
+            metric_id: "cpu_usage_percentage".to_string(),
+            logic: Logic::Gt,
+            value: "90".to_string(),
+        });
+        let backtest = replay.run(&condition, 0, 60, 5);
+        assert_eq!(
+            transitions(&backtest),
+            [
+                (AlertState::Firing, 10),
+                (AlertState::Resolved, 30),
+                (AlertState::Firing, 50)
+            ]
+        );
+        assert_eq!(backtest.evaluations, 13);
+        assert_eq!(backtest.firings, 2);
+        // The incident still open at the end is not counted
+        assert_eq!(backtest.firing_seconds, 20);
+        assert_eq!(backtest.events[0].observations[0].value, 95.0);
+    }
+
+    #[test]
+    fn gaps_in_the_data_keep_the_state() {
+        let replay = replay(&[(
+            "custom_queue",
+            &[(0, 0.0), (5, 20.0), (40, 20.0), (45, 20.0)],
+        )]);
+        let condition = Condition::Delta(Change {
+            metric_id: "custom_queue".to_string(),
+            window: 10,
+            logic: Logic::Gt,
+            value: "10".to_string(),
+        });
+        let backtest = replay.run(&condition, 0, 60, 5);
+        assert_eq!(
+            transitions(&backtest),
+            [(AlertState::Firing, 5), (AlertState::Resolved, 45)]
+        );
+        assert_eq!(backtest.firing_seconds, 40);
+    }
+
+    #[test]
+    fn absence_is_counted_from_the_start_of_the_replay() {
+        let replay = replay(&[]);
+        let condition = Condition::Absence(Absence {
+            metric_id: "custom_backup".to_string(),
+            window: 20,
+        });
+        let backtest = replay.run(&condition, 0, 60, 5);
+        assert_eq!(transitions(&backtest), [(AlertState::Firing, 25)]);
+    }
+
+    #[tokio::test]
+    async fn too_many_evaluations_are_rejected() {
+        let pool = SqlitePool::connect_lazy("sqlite::memory:").unwrap();
+        let request = BacktestRequest {
+            alert: alert("cpu_usage_percentage", "90"),
+            since: 0,
+            until: MAX_EVALUATIONS * DEFAULT_STEP as i64,
+            step: None,
+        };
+        let error = run(&pool, &request).await.unwrap_err();
+        assert!(error.contains("exceed the limit of 200000"), "{}", error);
+    }
+}
@@ -0,0 +1,707 @@
+use serde_json::{Value, json};
+
+use crate::models::{
+    alert::{Alert, Observation, Severity},
+    channel::{Discord, Gotify, Ntfy, PagerDuty, Slack, Telegram},
+    incident::AlertState,
+};
+
+// What the chat services show, rendered into each service's own layout
+struct Message {
+    title: String,
+    description: String,
+    color: u32,
+    fields: Vec<(&'static str, String)>,
+    link: Option<String>,
+}
+
+fn message(
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+    dashboard_url: Option<&str>,
+) -> Message {
+    let condition = alert.condition();
+    let title = match state {
+        AlertState::Firing => format!("[{}] Alert firing", alert.severity),
+        AlertState::Resolved => format!("[{}] Alert resolved", alert.severity),
+    };
+    let color = match (state, &alert.severity) {
+        (AlertState::Resolved, _) => 0x2EB67D,
+        (_, Severity::Critical) => 0xD00000,
+        (_, Severity::Warning) => 0xF2C744,
+        (_, Severity::Info) => 0x439FE0,
+        (_, Severity::Custom(_)) => 0x808080,
+    };
+
+    let mut fields = vec![("Severity", alert.severity.to_string())];
+    if let Some(observation) = observations.first() {
+        fields.push((
+            "Value",
+            format!("{} = {:.2}", observation.metric_id, observation.value),
+        ));
+    }
+    if let Some((logic, value)) = condition.limit() {
+        fields.push(("Threshold", format!("{} {}", logic, value)));
+    }
+    fields.push((
+        "Host",
+        sysinfo::System::host_name().unwrap_or_else(|| "unknown".to_string()),
+    ));
+
+    Message {
+        title,
+        description: condition.to_string(),
+        color,
+        fields,
+        link: dashboard_url.map(|url| url.to_string()),
+    }
+}
+
+impl Message {
+    // Plain text body for services without rich layouts
+    fn text(&self) -> String {
+        let mut lines = vec![self.description.clone()];
+        lines.extend(
+            self.fields
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value)),
+        );
+        lines.join("\n")
+    }
+}
+
+pub fn slack_payload(
+    slack: &Slack,
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+    dashboard_url: Option<&str>,
+) -> Value {
+    let message = message(alert, observations, state, dashboard_url);
+    let fields: Vec<Value> = message
+        .fields
+        .iter()
+        .map(|(title, value)| json!({ "title": title, "value": value, "short": true }))
+        .collect();
+    let mut payload = json!({
+        "text": format!("{}: {}", message.title, message.description),
+        "attachments": [{
+            "color": format!("#{:06X}", message.color),
+            "title": message.title,
+            "title_link": message.link,
+            "text": message.description,
+            "fields": fields,
+            "footer": "sciigilo",
+            "ts": chrono::Utc::now().timestamp(),
+        }],
+    });
+    if let Some(channel) = &slack.channel {
+        payload["channel"] = json!(channel);
+    }
+    if let Some(username) = &slack.username {
+        payload["username"] = json!(username);
+    }
+    payload
+}
+
+pub fn discord_payload(
+    discord: &Discord,
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+    dashboard_url: Option<&str>,
+) -> Value {
+    let message = message(alert, observations, state, dashboard_url);
+    let fields: Vec<Value> = message
+        .fields
+        .iter()
+        .map(|(name, value)| json!({ "name": name, "value": value, "inline": true }))
+        .collect();
+    let mut payload = json!({
+        "embeds": [{
+            "title": message.title,
+            "url": message.link,
+            "description": message.description,
+            "color": message.color,
+            "fields": fields,
+            "footer": { "text": "sciigilo" },
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        }],
+    });
+    if let Some(username) = &discord.username {
+        payload["username"] = json!(username);
+    }
+    payload
+}
+
+// Telegram has no priorities, info alerts are delivered silently instead
+pub fn telegram_payload(
+    telegram: &Telegram,
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+    dashboard_url: Option<&str>,
+) -> Value {
+    let message = message(alert, observations, state, dashboard_url);
+    let mut text = format!(
+        "<b>{}</b>\n{}",
+        escape_html(&message.title),
+        escape_html(&message.text())
+    );
+    if let Some(link) = &message.link {
+        text.push_str(&format!(
+            "\n<a href=\"{}\">Dashboard</a>",
+            escape_html(link)
+        ));
+    }
+    json!({
+        "chat_id": telegram.chat_id,
+        "text": text,
+        "parse_mode": "HTML",
+        "disable_notification": state == AlertState::Resolved || alert.severity == Severity::Info,
+    })
+}
+
+// ntfy priorities: 1 min, 2 low, 3 default, 4 high, 5 urgent
+pub fn ntfy_payload(
+    ntfy: &Ntfy,
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+    dashboard_url: Option<&str>,
+) -> Value {
+    let message = message(alert, observations, state, dashboard_url);
+    let priority = match (state, &alert.severity) {
+        (AlertState::Resolved, _) => 2,
+        (_, Severity::Critical) => 5,
+        (_, Severity::Warning) => 4,
+        _ => 3,
+    };
+    let mut tags = ntfy.tags.clone();
+    tags.push(match state {
+        AlertState::Firing => "rotating_light".to_string(),
+        AlertState::Resolved => "white_check_mark".to_string(),
+    });
+    let mut payload = json!({
+        "topic": ntfy.topic,
+        "title": message.title,
+        "message": message.text(),
+        "priority": ntfy.priority.unwrap_or(priority),
+        "tags": tags,
+    });
+    if let Some(link) = message.link {
+        payload["click"] = json!(link);
+    }
+    payload
+}
+
+// Gotify priorities run from 0 to 10, clients treat 8 and above as urgent
+pub fn gotify_payload(
+    gotify: &Gotify,
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+    dashboard_url: Option<&str>,
+) -> Value {
+    let message = message(alert, observations, state, dashboard_url);
+    let priority = match (state, &alert.severity) {
+        (AlertState::Resolved, _) => 2,
+        (_, Severity::Critical) => 8,
+        (_, Severity::Warning) => 5,
+        _ => 4,
+    };
+    let mut payload = json!({
+        "title": message.title,
+        "message": message.text(),
+        "priority": gotify.priority.unwrap_or(priority),
+    });
+    if let Some(link) = message.link {
+        payload["extras"] = json!({ "client::notification": { "click": { "url": link } } });
+    }
+    payload
+}
+
+// `body` is the fallback for clients that ignore `formatted_body`
+pub fn matrix_payload(
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+    dashboard_url: Option<&str>,
+) -> Value {
+    let message = message(alert, observations, state, dashboard_url);
+    let mut body = format!("{}\n{}", message.title, message.text());
+    let mut formatted_body = format!(
+        "<h4><font color=\"#{:06X}\">{}</font></h4>{}",
+        message.color,
+        escape_html(&message.title),
+        escape_html(&message.text()).replace('\n', "<br>")
+    );
+    if let Some(link) = &message.link {
+        body.push_str(&format!("\n{}", link));
+        formatted_body.push_str(&format!(
+            "<br><a href=\"{}\">Dashboard</a>",
+            escape_html(link)
+        ));
+    }
+    json!({
+        "msgtype": "m.notice",
+        "body": body,
+        "format": "org.matrix.custom.html",
+        "formatted_body": formatted_body,
+    })
+}
+
+// Adaptive Cards only know a few named colors instead of hex values
+pub fn teams_payload(
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+    dashboard_url: Option<&str>,
+) -> Value {
+    let message = message(alert, observations, state, dashboard_url);
+    let color = match (state, &alert.severity) {
+        (AlertState::Resolved, _) => "Good",
+        (_, Severity::Critical) => "Attention",
+        (_, Severity::Warning) => "Warning",
+        (_, Severity::Info) => "Accent",
+        (_, Severity::Custom(_)) => "Default",
+    };
+    let facts: Vec<Value> = message
+        .fields
+        .iter()
+        .map(|(title, value)| json!({ "title": title, "value": value }))
+        .collect();
+    let mut card = json!({
+        "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+        "type": "AdaptiveCard",
+        "version": "1.4",
+        "body": [
+            { "type": "TextBlock", "text": message.title, "weight": "Bolder", "size": "Medium", "color": color },
+            { "type": "TextBlock", "text": message.description, "wrap": true },
+            { "type": "FactSet", "facts": facts },
+        ],
+    });
+    if let Some(link) = message.link {
+        card["actions"] =
+            json!([{ "type": "Action.OpenUrl", "title": "Open dashboard", "url": link }]);
+    }
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "content": card,
+        }],
+    })
+}
+
+// The alert id is the dedup key, so every event of an alert lands on the same upstream incident
+pub fn pagerduty_payload(
+    pagerduty: &PagerDuty,
+    alert: &Alert,
+    observations: &[Observation],
+    event_action: &str,
+    dashboard_url: Option<&str>,
+) -> Value {
+    let mut payload = json!({
+        "routing_key": pagerduty.routing_key,
+        "event_action": event_action,
+        "dedup_key": alert.id.to_string(),
+    });
+    // Acknowledge and resolve events only need the key
+    if event_action != "trigger" {
+        return payload;
+    }
+    let message = message(alert, observations, AlertState::Firing, dashboard_url);
+    let severity = match alert.severity {
+        Severity::Critical => "critical",
+        Severity::Warning => "warning",
+        Severity::Info => "info",
+        Severity::Custom(_) => "error",
+    };
+    let details: serde_json::Map<String, Value> = message
+        .fields
+        .iter()
+        .map(|(name, value)| (name.to_string(), json!(value)))
+        .collect();
+    payload["payload"] = json!({
+        "summary": format!("{}: {}", message.title, message.description),
+        "source": pagerduty.source.clone().unwrap_or_else(|| {
+            sysinfo::System::host_name().unwrap_or_else(|| "sciigilo".to_string())
+        }),
+        "severity": severity,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "component": alert.metric_id,
+        "custom_details": details,
+    });
+    if let Some(link) = message.link {
+        payload["links"] = json!([{ "href": link, "text": "Dashboard" }]);
+    }
+    payload
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{Json, Router, extract::State, routing::post};
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::models::channel::ChannelKind;
+    use crate::utils::notifier::tests::{alert, notifier};
+
+    const DASHBOARD: &str = "http://dashboard.local";
+
+    fn critical() -> (Alert, Vec<Observation>) {
+        let mut alert = alert("cpu_usage_percentage", "90");
+        alert.severity = Severity::Critical;
+        let observations = vec![Observation {
+            metric_id: "cpu_usage_percentage".to_string(),
+            value: 97.5,
+            baseline: None,
+        }];
+        (alert, observations)
+    }
+
+    #[test]
+    fn slack_firing_has_severity_colour_and_dashboard_link() {
+        let (alert, observations) = critical();
+        let slack = Slack {
+            channel: Some("#ops".to_string()),
+            ..Default::default()
+        };
+        let payload = slack_payload(
+            &slack,
+            &alert,
+            &observations,
+            AlertState::Firing,
+            Some(DASHBOARD),
+        );
+        let attachment = &payload["attachments"][0];
+        assert_eq!(attachment["color"], "#D00000");
+        assert_eq!(attachment["title"], "[critical] Alert firing");
+        assert_eq!(attachment["title_link"], DASHBOARD);
+        assert_eq!(attachment["text"], "cpu_usage_percentage > 90");
+        assert_eq!(
+            attachment["fields"][1]["value"],
+            "cpu_usage_percentage = 97.50"
+        );
+        assert_eq!(payload["channel"], "#ops");
+    }
+
+    #[test]
+    fn slack_resolved_is_green() {
+        let (alert, observations) = critical();
+        let payload = slack_payload(
+            &Slack::default(),
+            &alert,
+            &observations,
+            AlertState::Resolved,
+            None,
+        );
+        let attachment = &payload["attachments"][0];
+        assert_eq!(attachment["color"], "#2EB67D");
+        assert_eq!(attachment["title"], "[critical] Alert resolved");
+        assert!(attachment["title_link"].is_null());
+        assert!(payload.get("channel").is_none());
+    }
+
+    #[test]
+    fn discord_firing_has_severity_colour_and_dashboard_link() {
+        let (mut alert, observations) = critical();
+        alert.severity = Severity::Warning;
+        let payload = discord_payload(
+            &Discord::default(),
+            &alert,
+            &observations,
+            AlertState::Firing,
+            Some(DASHBOARD),
+        );
+        let embed = &payload["embeds"][0];
+        assert_eq!(embed["color"], 0xF2C744);
+        assert_eq!(embed["title"], "[warning] Alert firing");
+        assert_eq!(embed["url"], DASHBOARD);
+        assert_eq!(embed["fields"][2]["value"], "> 90");
+    }
+
+    #[test]
+    fn discord_resolved_is_green() {
+        let (alert, observations) = critical();
+        let discord = Discord {
+            username: Some("sciigilo".to_string()),
+            ..Default::default()
+        };
+        let payload = discord_payload(
+            &discord,
+            &alert,
+            &observations,
+            AlertState::Resolved,
+            Some(DASHBOARD),
+        );
+        let embed = &payload["embeds"][0];
+        assert_eq!(embed["color"], 0x2EB67D);
+        assert_eq!(embed["title"], "[critical] Alert resolved");
+        assert_eq!(payload["username"], "sciigilo");
+    }
+
+    #[test]
+    fn telegram_escapes_html_and_silences_info_and_resolved() {
+        let (alert, observations) = critical();
+        let telegram = Telegram {
+            chat_id: "-100123".to_string(),
+            ..Default::default()
+        };
+        let payload = telegram_payload(
+            &telegram,
+            &alert,
+            &observations,
+            AlertState::Firing,
+            Some("http://dashboard.local/?a=1&b=2"),
+        );
+        let text = payload["text"].as_str().unwrap();
+        assert!(text.starts_with(
+            "<b>[critical] Alert firing</b>\ncpu_usage_percentage &gt; 90\nSeverity: critical\n"
+        ));
+        assert!(text.contains("Value: cpu_usage_percentage = 97.50\nThreshold: &gt; 90\n"));
+        assert!(text.ends_with("\n<a href=\"http://dashboard.local/?a=1&amp;b=2\">Dashboard</a>"));
+        assert_eq!(payload["chat_id"], "-100123");
+        assert_eq!(payload["parse_mode"], "HTML");
+        assert_eq!(payload["disable_notification"], false);
+
+        let resolved =
+            telegram_payload(&telegram, &alert, &observations, AlertState::Resolved, None);
+        assert_eq!(resolved["disable_notification"], true);
+        assert!(!resolved["text"].as_str().unwrap().contains("Dashboard"));
+        let mut info = alert.clone();
+        info.severity = Severity::Info;
+        let info = telegram_payload(&telegram, &info, &observations, AlertState::Firing, None);
+        assert_eq!(info["disable_notification"], true);
+    }
+
+    #[test]
+    fn ntfy_priority_follows_severity_unless_overridden() {
+        let (alert, observations) = critical();
+        let ntfy = Ntfy {
+            topic: "alerts".to_string(),
+            tags: vec!["server".to_string()],
+            ..Default::default()
+        };
+        let payload = ntfy_payload(
+            &ntfy,
+            &alert,
+            &observations,
+            AlertState::Firing,
+            Some(DASHBOARD),
+        );
+        assert_eq!(payload["topic"], "alerts");
+        assert_eq!(payload["title"], "[critical] Alert firing");
+        assert!(
+            payload["message"]
+                .as_str()
+                .unwrap()
+                .starts_with("cpu_usage_percentage > 90\nSeverity: critical\n")
+        );
+        assert_eq!(payload["priority"], 5);
+        assert_eq!(payload["tags"], json!(["server", "rotating_light"]));
+        assert_eq!(payload["click"], DASHBOARD);
+
+        let resolved = ntfy_payload(&ntfy, &alert, &observations, AlertState::Resolved, None);
+        assert_eq!(resolved["priority"], 2);
+        assert_eq!(resolved["tags"], json!(["server", "white_check_mark"]));
+        assert!(resolved.get("click").is_none());
+
+        let ntfy = Ntfy {
+            priority: Some(1),
+            ..ntfy
+        };
+        let payload = ntfy_payload(&ntfy, &alert, &observations, AlertState::Firing, None);
+        assert_eq!(payload["priority"], 1);
+    }
+
+    #[test]
+    fn gotify_priority_follows_severity_unless_overridden() {
+        let (mut alert, observations) = critical();
+        let payload = gotify_payload(
+            &Gotify::default(),
+            &alert,
+            &observations,
+            AlertState::Firing,
+            Some(DASHBOARD),
+        );
+        assert_eq!(payload["title"], "[critical] Alert firing");
+        assert!(
+            payload["message"]
+                .as_str()
+                .unwrap()
+                .contains("Value: cpu_usage_percentage = 97.50")
+        );
+        assert_eq!(payload["priority"], 8);
+        assert_eq!(
+            payload["extras"]["client::notification"]["click"]["url"],
+            DASHBOARD
+        );
+
+        alert.severity = Severity::Warning;
+        let warning = gotify_payload(
+            &Gotify::default(),
+            &alert,
+            &observations,
+            AlertState::Firing,
+            None,
+        );
+        assert_eq!(warning["priority"], 5);
+        assert!(warning.get("extras").is_none());
+        let resolved = gotify_payload(
+            &Gotify::default(),
+            &alert,
+            &observations,
+            AlertState::Resolved,
+            None,
+        );
+        assert_eq!(resolved["priority"], 2);
+        let gotify = Gotify {
+            priority: Some(10),
+            ..Default::default()
+        };
+        let payload = gotify_payload(&gotify, &alert, &observations, AlertState::Resolved, None);
+        assert_eq!(payload["priority"], 10);
+    }
+
+    #[test]
+    fn matrix_notice_has_plain_and_html_body() {
+        let (alert, observations) = critical();
+        let payload = matrix_payload(&alert, &observations, AlertState::Firing, Some(DASHBOARD));
+        assert_eq!(payload["msgtype"], "m.notice");
+        assert_eq!(payload["format"], "org.matrix.custom.html");
+        let body = payload["body"].as_str().unwrap();
+        assert!(body.starts_with("[critical] Alert firing\ncpu_usage_percentage > 90\n"));
+        assert!(body.ends_with("\nhttp://dashboard.local"));
+        let html = payload["formatted_body"].as_str().unwrap();
+        assert!(html.starts_with(
+            "<h4><font color=\"#D00000\">[critical] Alert firing</font></h4>cpu_usage_percentage &gt; 90<br>Severity: critical<br>"
+        ));
+        assert!(html.ends_with("<br><a href=\"http://dashboard.local\">Dashboard</a>"));
+
+        let resolved = matrix_payload(&alert, &observations, AlertState::Resolved, None);
+        let html = resolved["formatted_body"].as_str().unwrap();
+        assert!(html.starts_with("<h4><font color=\"#2EB67D\">"));
+        assert!(!html.contains("Dashboard"));
+    }
+
+    #[test]
+    fn teams_card_uses_named_colours_and_facts() {
+        let (alert, observations) = critical();
+        let payload = teams_payload(&alert, &observations, AlertState::Firing, Some(DASHBOARD));
+        assert_eq!(payload["type"], "message");
+        let attachment = &payload["attachments"][0];
+        assert_eq!(
+            attachment["contentType"],
+            "application/vnd.microsoft.card.adaptive"
+        );
+        let card = &attachment["content"];
+        assert_eq!(card["type"], "AdaptiveCard");
+        assert_eq!(card["body"][0]["text"], "[critical] Alert firing");
+        assert_eq!(card["body"][0]["color"], "Attention");
+        assert_eq!(card["body"][1]["text"], "cpu_usage_percentage > 90");
+        assert_eq!(
+            card["body"][2]["facts"][1],
+            json!({ "title": "Value", "value": "cpu_usage_percentage = 97.50" })
+        );
+        assert_eq!(card["actions"][0]["type"], "Action.OpenUrl");
+        assert_eq!(card["actions"][0]["url"], DASHBOARD);
+
+        let resolved = teams_payload(&alert, &observations, AlertState::Resolved, None);
+        let card = &resolved["attachments"][0]["content"];
+        assert_eq!(card["body"][0]["color"], "Good");
+        assert!(card.get("actions").is_none());
+    }
+
+    // Accepts JSON posts on `/hook` and passes on every body
+    async fn hook() -> (String, mpsc::UnboundedReceiver<Value>) {
+        let (sender, received) = mpsc::unbounded_channel();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    |State(sender): State<mpsc::UnboundedSender<Value>>,
+                     Json(body): Json<Value>| async move {
+                        sender.send(body).unwrap();
+                        "ok"
+                    },
+                ),
+            )
+            .with_state(sender);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, received)
+    }
+
+    async fn next(received: &mut mpsc::UnboundedReceiver<Value>) -> Value {
+        tokio::time::timeout(Duration::from_secs(10), received.recv())
+            .await
+            .expect("no request within 10 seconds")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn slack_message_is_delivered_to_the_webhook() {
+        let (url, mut received) = hook().await;
+
+        let notifier = notifier(Some(DASHBOARD)).await;
+        let (alert, observations) = critical();
+        let kind = ChannelKind::Slack(Slack {
+            webhook_url: url,
+            ..Default::default()
+        });
+        notifier.notify(&kind, &alert, &observations, AlertState::Firing);
+
+        let body = next(&mut received).await;
+        assert_eq!(body["attachments"][0]["color"], "#D00000");
+        assert_eq!(body["attachments"][0]["title_link"], DASHBOARD);
+    }
+
+    #[tokio::test]
+    async fn pagerduty_events_of_an_alert_share_the_dedup_key() {
+        let (url, mut received) = hook().await;
+        let notifier = notifier(Some(DASHBOARD)).await;
+        let (mut alert, observations) = critical();
+        alert.id = Uuid::new_v4();
+        let kind = ChannelKind::PagerDuty(PagerDuty {
+            url,
+            routing_key: "R0UT1NG".to_string(),
+            source: Some("web-1".to_string()),
+        });
+
+        notifier.notify(&kind, &alert, &observations, AlertState::Firing);
+        let trigger = next(&mut received).await;
+        notifier.acknowledge(&kind, &alert);
+        let acknowledge = next(&mut received).await;
+        notifier.notify(&kind, &alert, &observations, AlertState::Resolved);
+        let resolve = next(&mut received).await;
+
+        assert_eq!(trigger["event_action"], "trigger");
+        assert_eq!(trigger["routing_key"], "R0UT1NG");
+        assert_eq!(trigger["payload"]["severity"], "critical");
+        assert_eq!(trigger["payload"]["source"], "web-1");
+        assert_eq!(trigger["links"][0]["href"], DASHBOARD);
+        assert_eq!(acknowledge["event_action"], "acknowledge");
+        assert_eq!(resolve["event_action"], "resolve");
+        for event in [&trigger, &acknowledge, &resolve] {
+            assert_eq!(event["dedup_key"], alert.id.to_string());
+        }
+        // Only the trigger describes the incident
+        assert!(acknowledge.get("payload").is_none());
+        assert!(resolve.get("payload").is_none());
+    }
+}
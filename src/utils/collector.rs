@@ -1,31 +1,64 @@
-use std::{sync::Arc, time};
+use std::{
+    sync::{Arc, LazyLock},
+    time,
+};
 
 use sqlx::SqlitePool;
 
 use crate::models::{cpu, disk, mem, metrics::MetricType};
 
-use super::db;
+use super::{db, mqtt::Mqtt};
+
+// Mount usage changes slowly, forecasts over hours do not need a row every second
+const MOUNT_SAMPLE_INTERVAL: i64 = 60;
+// In-memory and image file systems that never fill up like a disk does
+const VIRTUAL_FILE_SYSTEMS: &[&str] = &[
+    "tmpfs", "devtmpfs", "ramfs", "overlay", "squashfs", "proc", "sysfs", "devfs", "autofs",
+    "efivarfs",
+];
+
+// Process start, no metric can have reported while sciigilo was not running
+pub static STARTED_AT: LazyLock<i64> = LazyLock::new(|| chrono::Utc::now().timestamp());
 
 pub struct MetricsCollector {
     pool: Arc<SqlitePool>,
+    mqtt: Option<Mqtt>,
     sysinfo_instance: sysinfo::System,
     disks_instance: sysinfo::Disks,
+    last_mount_sample: Option<i64>,
 }
 
 impl MetricsCollector {
-    pub fn new(pool: Arc<SqlitePool>) -> MetricsCollector {
+    pub fn new(pool: Arc<SqlitePool>, mqtt: Option<Mqtt>) -> MetricsCollector {
+        LazyLock::force(&STARTED_AT);
         MetricsCollector {
             pool,
+            mqtt,
             sysinfo_instance: sysinfo::System::new(),
             disks_instance: sysinfo::Disks::new_with_refreshed_list(),
+            last_mount_sample: None,
         }
     }
 
     pub async fn collect_metrics(&mut self) {
+        let now = chrono::Utc::now().timestamp();
+        let sample_mounts = self
+            .last_mount_sample
+            .is_none_or(|last| now - last >= MOUNT_SAMPLE_INTERVAL);
+        self.refresh_disks(sample_mounts);
+
         let cpu_metrics = self.get_cpu_metrics().await.unwrap_or_default();
         let memory_metrics = self.get_memory_metrics().await.unwrap_or_default();
         let disk_metrics = self.get_disk_metrics().await.unwrap_or_default();
 
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish_metrics(&[
+                MetricType::Cpu(cpu_metrics.clone()),
+                MetricType::Memory(memory_metrics.clone()),
+                MetricType::Disk(disk_metrics.clone()),
+            ]);
+        }
+
         if let Err(e) = db::insert_metrics(&self.pool, MetricType::Cpu(cpu_metrics)).await {
             eprintln!("Failed to insert CPU metrics: {:?}", e);
         }
@@ -35,6 +68,24 @@ impl MetricsCollector {
         if let Err(e) = db::insert_metrics(&self.pool, MetricType::Disk(disk_metrics)).await {
             eprintln!("Failed to insert disk metrics: {:?}", e);
         };
+        if !sample_mounts {
+            return;
+        }
+        self.last_mount_sample = Some(now);
+        for (mount_point, mount_metrics) in self.get_mount_metrics() {
+            if let Err(e) = db::insert_mount_metrics(&self.pool, &mount_point, mount_metrics).await
+            {
+                eprintln!("Failed to insert metrics for {}: {:?}", mount_point, e);
+            }
+        }
+    }
+
+    // The overall disk metrics read the first disk every cycle, the other mounts only when sampled
+    fn refresh_disks(&mut self, all: bool) {
+        let count = if all { usize::MAX } else { 1 };
+        for disk in self.disks_instance.iter_mut().take(count) {
+            disk.refresh();
+        }
     }
 
     async fn get_cpu_metrics(&mut self) -> Result<cpu::CpuMetrics, String> {
@@ -74,15 +125,50 @@ impl MetricsCollector {
     }
 
     async fn get_disk_metrics(&mut self) -> Result<disk::DiskMetrics, String> {
-        let disk = match self.disks_instance.iter_mut().next() {
+        let disk = match self.disks_instance.iter().next() {
             Some(disk) => disk,
             None => return Err("No disks found".to_string()),
         };
 
-        disk.refresh();
         Ok(disk::DiskMetrics::new(
             (disk.total_space() / 1048576) as u32,
             (disk.available_space() / 1048576) as u32,
         ))
     }
+
+    // Per mount point disk usage, kept separately for forecasting
+    fn get_mount_metrics(&self) -> Vec<(String, disk::DiskMetrics)> {
+        self.disks_instance
+            .iter()
+            .filter(|disk| is_real_mount(&disk.file_system().to_string_lossy(), disk.total_space()))
+            .map(|disk| {
+                (
+                    disk.mount_point().display().to_string(),
+                    disk::DiskMetrics::new(
+                        (disk.total_space() / 1048576) as u32,
+                        (disk.available_space() / 1048576) as u32,
+                    ),
+                )
+            })
+            .collect()
+    }
+}
+
+fn is_real_mount(file_system: &str, total_space: u64) -> bool {
+    total_space > 0 && !VIRTUAL_FILE_SYSTEMS.contains(&file_system)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_empty_and_virtual_mounts() {
+        assert!(is_real_mount("ext4", 1 << 30));
+        assert!(is_real_mount("btrfs", 1 << 30));
+        assert!(!is_real_mount("ext4", 0));
+        assert!(!is_real_mount("tmpfs", 1 << 30));
+        assert!(!is_real_mount("overlay", 1 << 30));
+        assert!(!is_real_mount("squashfs", 1 << 20));
+    }
 }
@@ -1,9 +1,11 @@
-use crate::models::alert::Alert;
-use std::env;
-use tokio::{
-    fs::{self, File},
-    io::AsyncReadExt,
+use crate::models::{
+    alert::Alert, channel::Channel, escalation::EscalationPolicy, routing::NotificationRoute,
 };
+use axum::extract::FromRef;
+use serde::{Serialize, de::DeserializeOwned};
+use std::{env, sync::Arc};
+use tokio::{fs, sync::Mutex};
+use uuid::Uuid;
 
 pub fn load_env() {
     match dotenvy::dotenv() {
@@ -16,9 +18,26 @@ pub struct EnvConfig {
     pub db_file_path: String,
     pub port: u16,
     pub alerts_file_path: String,
+    pub routing_file_path: String,
+    pub channels_file_path: String,
+    pub escalations_file_path: String,
     pub log_file_path: String,
     pub retention_period: u32,
     pub domain: Option<String>,
+    pub public_url: Option<String>,
+    pub http_timeout: u64,
+    pub http_retries: u32,
+    pub exec_actions_enabled: bool,
+    pub mqtt_url: Option<String>,
+    pub mqtt_prefix: String,
+    pub mqtt_discovery: bool,
+    pub mqtt_discovery_prefix: String,
+    pub syslog_url: Option<String>,
+    pub syslog_facility: String,
+    pub syslog_only: bool,
+    pub log_max_size: u64,
+    pub log_max_age: u32,
+    pub log_keep: u32,
 }
 
 impl EnvConfig {
@@ -27,9 +46,26 @@ impl EnvConfig {
             db_file_path: String::new(),
             port: 3000,
             alerts_file_path: String::new(),
+            routing_file_path: String::new(),
+            channels_file_path: String::new(),
+            escalations_file_path: String::new(),
             log_file_path: String::new(),
             retention_period: 1,
             domain: None,
+            public_url: None,
+            http_timeout: 10,
+            http_retries: 3,
+            exec_actions_enabled: false,
+            mqtt_url: None,
+            mqtt_prefix: "sciigilo".to_string(),
+            mqtt_discovery: false,
+            mqtt_discovery_prefix: "homeassistant".to_string(),
+            syslog_url: None,
+            syslog_facility: "local0".to_string(),
+            syslog_only: false,
+            log_max_size: 10 * 1048576,
+            log_max_age: 7,
+            log_keep: 5,
         }
     }
 
@@ -58,6 +94,27 @@ impl EnvConfig {
                 false => format!("{}/sciigilo/alerts.json", config_dir.display()),
             }
         });
+        let routing_path = env::var("ROUTING_FILE").unwrap_or_else(|_| {
+            let config_dir = dirs::config_dir().expect("Failed to get config directory");
+            match is_docker {
+                true => "/data/routing.json".to_string(),
+                false => format!("{}/sciigilo/routing.json", config_dir.display()),
+            }
+        });
+        let channels_path = env::var("CHANNELS_FILE").unwrap_or_else(|_| {
+            let config_dir = dirs::config_dir().expect("Failed to get config directory");
+            match is_docker {
+                true => "/data/channels.json".to_string(),
+                false => format!("{}/sciigilo/channels.json", config_dir.display()),
+            }
+        });
+        let escalations_path = env::var("ESCALATIONS_FILE").unwrap_or_else(|_| {
+            let config_dir = dirs::config_dir().expect("Failed to get config directory");
+            match is_docker {
+                true => "/data/escalations.json".to_string(),
+                false => format!("{}/sciigilo/escalations.json", config_dir.display()),
+            }
+        });
         let log_path = env::var("LOG_FILE").unwrap_or_else(|_| {
             let config_dir = dirs::config_dir().expect("Failed to get config directory");
             match is_docker {
@@ -75,106 +132,436 @@ impl EnvConfig {
             retention_period = 1;
         }
 
+        let mut http_timeout = env::var("HTTP_TIMEOUT")
+            .ok()
+            .and_then(|val| val.parse().ok())
+            .unwrap_or(10);
+        if http_timeout < 1 {
+            eprintln!("HTTP timeout must be at least 1 second, setting it to 1 second");
+            http_timeout = 1;
+        }
+        let http_retries = env::var("HTTP_RETRIES")
+            .ok()
+            .and_then(|val| val.parse().ok())
+            .unwrap_or(3);
+
+        // Anyone who can edit alerts could run commands, so this has to be switched on explicitly
+        let exec_actions_enabled = env::var("EXEC_ACTIONS_ENABLED")
+            .map(|val| matches!(val.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+
+        let mqtt_url = env::var("MQTT_URL").ok().filter(|val| !val.is_empty());
+        let mqtt_prefix = env::var("MQTT_TOPIC_PREFIX").unwrap_or_else(|_| "sciigilo".to_string());
+        let mqtt_discovery = env::var("MQTT_DISCOVERY")
+            .map(|val| matches!(val.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        let mqtt_discovery_prefix =
+            env::var("MQTT_DISCOVERY_PREFIX").unwrap_or_else(|_| "homeassistant".to_string());
+
+        let syslog_url = env::var("SYSLOG_URL").ok().filter(|val| !val.is_empty());
+        let syslog_facility = env::var("SYSLOG_FACILITY").unwrap_or_else(|_| "local0".to_string());
+        let syslog_only = env::var("SYSLOG_ONLY")
+            .map(|val| matches!(val.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+
+        let mut log_max_size: u64 = env::var("LOG_MAX_SIZE")
+            .ok()
+            .and_then(|val| val.parse().ok())
+            .unwrap_or(10);
+        if log_max_size < 1 {
+            eprintln!("Log size limit must be at least 1 MB, setting it to 1 MB");
+            log_max_size = 1;
+        }
+        let mut log_max_age = env::var("LOG_MAX_AGE")
+            .ok()
+            .and_then(|val| val.parse().ok())
+            .unwrap_or(7);
+        if log_max_age < 1 {
+            eprintln!("Log age limit must be at least 1 day, setting it to 1 day");
+            log_max_age = 1;
+        }
+        let log_keep = env::var("LOG_KEEP")
+            .ok()
+            .and_then(|val| val.parse().ok())
+            .unwrap_or(5);
+
         let origin = env::var("ORIGIN").ok();
+        // Where notifications link back to, the CORS origin is usually the dashboard as well
+        let public_url = env::var("PUBLIC_URL").ok().or_else(|| origin.clone());
         self.db_file_path = db_path;
         self.port = port;
         self.alerts_file_path = alerts_path;
+        self.routing_file_path = routing_path;
+        self.channels_file_path = channels_path;
+        self.escalations_file_path = escalations_path;
         self.log_file_path = log_path;
         self.retention_period = retention_period;
         self.domain = origin;
+        self.public_url = public_url;
+        self.http_timeout = http_timeout;
+        self.http_retries = http_retries;
+        self.exec_actions_enabled = exec_actions_enabled;
+        self.mqtt_url = mqtt_url;
+        self.mqtt_prefix = mqtt_prefix;
+        self.mqtt_discovery = mqtt_discovery;
+        self.mqtt_discovery_prefix = mqtt_discovery_prefix;
+        self.syslog_url = syslog_url;
+        self.syslog_facility = syslog_facility;
+        self.syslog_only = syslog_only;
+        self.log_max_size = log_max_size * 1048576;
+        self.log_max_age = log_max_age;
+        self.log_keep = log_keep;
+    }
+}
+
+// An entry of one of the JSON config files
+pub trait StoredItem: Serialize + DeserializeOwned + Clone {
+    // Plural name used in log messages
+    const NAME: &'static str;
+
+    fn id(&self) -> Uuid;
+    fn file_path(env: &EnvConfig) -> &str;
+}
+
+impl StoredItem for Alert {
+    const NAME: &'static str = "alerts";
+
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn file_path(env: &EnvConfig) -> &str {
+        &env.alerts_file_path
     }
 }
 
+impl StoredItem for NotificationRoute {
+    const NAME: &'static str = "routes";
+
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn file_path(env: &EnvConfig) -> &str {
+        &env.routing_file_path
+    }
+}
+
+impl StoredItem for Channel {
+    const NAME: &'static str = "channels";
+
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn file_path(env: &EnvConfig) -> &str {
+        &env.channels_file_path
+    }
+}
+
+impl StoredItem for EscalationPolicy {
+    const NAME: &'static str = "escalation policies";
+
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn file_path(env: &EnvConfig) -> &str {
+        &env.escalations_file_path
+    }
+}
+
+// A JSON array file shared by every clone. The lock is held from re-reading the file (so manual
+// edits are picked up) until the changed list is written back, so concurrent saves never
+// overwrite each other.
 #[derive(Clone)]
-pub struct AlertConfig {
-    alerts: Vec<Alert>,
-    file_path: String,
+pub struct JsonStore<T> {
+    items: Arc<Mutex<Vec<T>>>,
+    file_path: Arc<str>,
 }
 
-impl AlertConfig {
-    pub fn new(env: &EnvConfig) -> AlertConfig {
-        AlertConfig {
-            alerts: vec![],
-            file_path: env.alerts_file_path.clone(),
+pub type AlertConfig = JsonStore<Alert>;
+pub type RoutingConfig = JsonStore<NotificationRoute>;
+pub type ChannelConfig = JsonStore<Channel>;
+pub type EscalationConfig = JsonStore<EscalationPolicy>;
+
+impl<T: StoredItem> JsonStore<T> {
+    pub fn new(env: &EnvConfig) -> JsonStore<T> {
+        JsonStore {
+            items: Arc::new(Mutex::new(vec![])),
+            file_path: T::file_path(env).into(),
         }
     }
 
-    pub async fn read_config(&mut self) {
-        if !fs::try_exists(&self.file_path).await.unwrap_or(false) {
-            if let Err(e) = self.save().await {
-                eprintln!("Failed to create initial config file: {}", e);
+    pub async fn read_config(&self) {
+        let mut items = self.items.lock().await;
+        self.reload(&mut items).await;
+    }
+
+    // Keeps the previous list when the file cannot be read
+    async fn reload(&self, items: &mut Vec<T>) {
+        if !fs::try_exists(&*self.file_path).await.unwrap_or(false) {
+            if let Err(e) = self.save(items).await {
+                eprintln!("Failed to create initial {} file: {}", T::NAME, e);
             }
             return;
         }
 
-        let mut file = match File::open(&self.file_path).await {
-            Ok(f) => f,
+        let content = match fs::read_to_string(&*self.file_path).await {
+            Ok(content) => content,
             Err(e) => {
-                eprintln!("Failed to open config file: {}", e);
+                eprintln!("Failed to read {} file: {}", T::NAME, e);
                 return;
             }
         };
 
-        let mut content = String::new();
-        if let Err(e) = file.read_to_string(&mut content).await {
-            eprintln!("Failed to read config file: {}", e);
-            return;
-        }
-
-        self.alerts = serde_json::from_str(&content).unwrap_or_else(|e| {
-            eprintln!("Failed to parse alerts config: {}", e);
+        *items = serde_json::from_str(&content).unwrap_or_else(|e| {
+            eprintln!("Failed to parse {} config: {}", T::NAME, e);
             vec![]
         });
     }
 
-    pub async fn save(&self) -> tokio::io::Result<()> {
-        if let Some(parent) = std::path::Path::new(&self.file_path).parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent).await?;
-            }
+    async fn save(&self, items: &[T]) -> tokio::io::Result<()> {
+        if let Some(parent) = std::path::Path::new(&*self.file_path).parent()
+            && !parent.exists()
+        {
+            fs::create_dir_all(parent).await?;
         }
-        let content = serde_json::to_string_pretty(&self.alerts).expect("Failed to save Json file");
-        fs::write(&self.file_path, content).await
+        let content = serde_json::to_string_pretty(items).expect("Failed to save Json file");
+        fs::write(&*self.file_path, content).await
     }
 
-    pub async fn get_alerts(&mut self) -> &Vec<Alert> {
-        self.read_config().await;
-        &self.alerts
+    // Re-reads the file, applies `change` and writes the result back under one lock
+    async fn modify<R>(&self, change: impl FnOnce(&mut Vec<T>) -> R) -> R {
+        let mut items = self.items.lock().await;
+        self.reload(&mut items).await;
+        let result = change(&mut items);
+        self.save(&items).await.unwrap_or_else(|e| {
+            eprintln!("Failed to save {}: {}", T::NAME, e);
+        });
+        result
     }
 
-    pub async fn add_alert(&mut self, alert: Alert) {
-        self.read_config().await;
-        self.alerts.push(alert);
-        self.save().await.unwrap_or_else(|e| {
-            eprintln!("Failed to save alerts: {}", e);
-        });
+    pub async fn get_all(&self) -> Vec<T> {
+        let mut items = self.items.lock().await;
+        self.reload(&mut items).await;
+        items.clone()
     }
 
-    pub async fn remove_alert(&mut self, uuid: &str) {
-        self.read_config().await;
-        if !self
+    pub async fn add(&self, item: T) {
+        self.modify(|items| items.push(item)).await
+    }
+
+    pub async fn remove(&self, uuid: &str) -> bool {
+        self.modify(|items| {
+            let count = items.len();
+            items.retain(|item| item.id().to_string() != uuid);
+            items.len() != count
+        })
+        .await
+    }
+
+    pub async fn update(&self, item: T) {
+        self.modify(|items| {
+            items.retain(|i| i.id() != item.id());
+            items.push(item);
+        })
+        .await
+    }
+
+    // Routes are matched in order, so the routing table is always replaced as a whole
+    pub async fn set_all(&self, new_items: Vec<T>) {
+        self.modify(|items| *items = new_items).await
+    }
+}
+
+// Every config file, shared by the routes that edit them and the watchtower
+#[derive(Clone)]
+pub struct ConfigState {
+    pub alerts: AlertConfig,
+    pub routing: RoutingConfig,
+    pub channels: ChannelConfig,
+    pub escalations: EscalationConfig,
+}
+
+impl ConfigState {
+    pub async fn read_config(env: &EnvConfig) -> ConfigState {
+        let state = ConfigState {
+            alerts: JsonStore::new(env),
+            routing: JsonStore::new(env),
+            channels: JsonStore::new(env),
+            escalations: JsonStore::new(env),
+        };
+        state.alerts.read_config().await;
+        state.routing.read_config().await;
+        state.channels.read_config().await;
+        state.escalations.read_config().await;
+        state
+    }
+
+    // Saved rules must not point at channels or escalation policies that do not exist
+    pub async fn check_references(
+        &self,
+        channels: &[Uuid],
+        escalation_policy: Option<Uuid>,
+    ) -> Result<(), String> {
+        let known = self.channels.get_all().await;
+        if let Some(id) = channels
+            .iter()
+            .find(|id| !known.iter().any(|c| c.id == **id))
+        {
+            return Err(format!("Channel with id {} not found", id));
+        }
+        if let Some(id) = escalation_policy
+            && !self.escalations.get_all().await.iter().any(|p| p.id == id)
+        {
+            return Err(format!("Escalation policy with id {} not found", id));
+        }
+        Ok(())
+    }
+
+    // Channels still used by a rule, route or escalation policy cannot be deleted
+    pub async fn channel_users(&self, id: Uuid) -> Option<String> {
+        if let Some(alert) = self
             .alerts
+            .get_all()
+            .await
             .iter()
-            .map(|a| a.id.to_string())
-            .any(|id| id == uuid)
+            .find(|a| a.channels.contains(&id))
         {
-            eprintln!("Alert with id {} not found", uuid);
-            println!("{:?}", self.alerts);
-            return;
+            return Some(format!("alert {}", alert.id));
         }
-        self.alerts.retain(|a| a.id.to_string() != uuid);
+        if let Some(route) = self
+            .routing
+            .get_all()
+            .await
+            .iter()
+            .find(|r| r.channels.contains(&id))
+        {
+            return Some(format!("route {}", route.id));
+        }
+        self.escalations
+            .get_all()
+            .await
+            .iter()
+            .find(|p| p.steps.iter().any(|s| s.channels.contains(&id)))
+            .map(|p| format!("escalation policy {}", p.id))
+    }
 
-        self.save().await.unwrap_or_else(|e| {
-            eprintln!("Failed to save alerts: {}", e);
-        });
+    pub async fn policy_users(&self, id: Uuid) -> Option<String> {
+        self.alerts
+            .get_all()
+            .await
+            .iter()
+            .find(|a| a.escalation_policy == Some(id))
+            .map(|a| format!("alert {}", a.id))
+    }
+}
+
+impl FromRef<ConfigState> for AlertConfig {
+    fn from_ref(state: &ConfigState) -> AlertConfig {
+        state.alerts.clone()
     }
+}
 
-    pub async fn update_alert(&mut self, alert: Alert) {
-        self.read_config().await;
-        self.alerts.retain(|a| a.id != alert.id);
-        self.alerts.push(alert);
-        self.save().await.unwrap_or_else(|e| {
-            eprintln!("Failed to save alerts: {}", e);
-        });
+impl FromRef<ConfigState> for RoutingConfig {
+    fn from_ref(state: &ConfigState) -> RoutingConfig {
+        state.routing.clone()
+    }
+}
+
+impl FromRef<ConfigState> for ChannelConfig {
+    fn from_ref(state: &ConfigState) -> ChannelConfig {
+        state.channels.clone()
+    }
+}
+
+impl FromRef<ConfigState> for EscalationConfig {
+    fn from_ref(state: &ConfigState) -> EscalationConfig {
+        state.escalations.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::escalation::EscalationStep;
+
+    fn env() -> EnvConfig {
+        let dir = std::env::temp_dir().join(format!("sciigilo-config-{}", Uuid::new_v4()));
+        let path = |name: &str| dir.join(name).display().to_string();
+        let mut env = EnvConfig::new();
+        env.alerts_file_path = path("alerts.json");
+        env.routing_file_path = path("routing.json");
+        env.channels_file_path = path("channels.json");
+        env.escalations_file_path = path("escalations.json");
+        env
+    }
+
+    #[tokio::test]
+    async fn concurrent_saves_keep_every_entry() {
+        let env = env();
+        let alerts = AlertConfig::new(&env);
+        let saves: Vec<_> = (0..20)
+            .map(|_| {
+                let alerts = alerts.clone();
+                tokio::spawn(async move { alerts.add(Alert::default()).await })
+            })
+            .collect();
+        for save in saves {
+            save.await.unwrap();
+        }
+        assert_eq!(alerts.get_all().await.len(), 20);
+        // A fresh store reads the same file
+        assert_eq!(AlertConfig::new(&env).get_all().await.len(), 20);
+    }
+
+    #[tokio::test]
+    async fn referenced_channels_and_policies_are_reported() {
+        let config = ConfigState::read_config(&env()).await;
+        let channel = Channel {
+            id: Uuid::new_v4(),
+            name: "ops".to_string(),
+            ..Default::default()
+        };
+        config.channels.add(channel.clone()).await;
+        let policy = EscalationPolicy {
+            id: Uuid::new_v4(),
+            name: "night".to_string(),
+            steps: vec![EscalationStep {
+                after: 600,
+                channels: vec![channel.id],
+            }],
+        };
+        config.escalations.add(policy.clone()).await;
+        assert_eq!(
+            config.channel_users(channel.id).await,
+            Some(format!("escalation policy {}", policy.id))
+        );
+        assert_eq!(config.policy_users(policy.id).await, None);
+
+        let alert = Alert {
+            id: Uuid::new_v4(),
+            escalation_policy: Some(policy.id),
+            ..Default::default()
+        };
+        config.alerts.add(alert.clone()).await;
+        assert_eq!(
+            config.policy_users(policy.id).await,
+            Some(format!("alert {}", alert.id))
+        );
+        assert!(
+            config
+                .check_references(&[channel.id], Some(policy.id))
+                .await
+                .is_ok()
+        );
+        assert!(
+            config
+                .check_references(&[Uuid::new_v4()], None)
+                .await
+                .is_err()
+        );
+        assert_eq!(config.channel_users(Uuid::new_v4()).await, None);
     }
 }
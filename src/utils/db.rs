@@ -1,10 +1,16 @@
 use crate::models::cpu::CpuMetrics;
+use crate::models::custom::CustomMetric;
+use crate::models::delivery::Delivery;
 use crate::models::disk::DiskMetrics;
+use crate::models::history::{AlertEvent, DailyCount, HistoryFilter, ResolveTime};
+use crate::models::incident::{AlertState, Incident, IncidentEvent};
 use crate::models::mem::MemoryMetrics;
 use crate::models::metrics::MetricType;
-use sqlx::{Row, SqlitePool, pool};
+use crate::models::silence::Silence;
+use sqlx::{Row, SqlitePool, sqlite::SqliteRow};
 use std::{path::Path, sync::Arc};
 use tokio::fs::OpenOptions;
+use uuid::Uuid;
 
 pub async fn connect(path: &str) -> Result<SqlitePool, sqlx::Error> {
     create_db_file_if_not_exists(path).await?;
@@ -33,6 +39,7 @@ async fn create_db_file_if_not_exists(path: &str) -> Result<(), sqlx::Error> {
             if !db_path.exists() {
                 OpenOptions::new()
                     .create(true)
+                    .truncate(false)
                     .write(true)
                     .open(db_path)
                     .await
@@ -92,9 +99,87 @@ fn get_init_query() -> &'static str {
             total_space INTEGER NOT NULL
         );
 
+        CREATE TABLE IF NOT EXISTS MountMetrics (
+            timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            mount_point TEXT NOT NULL,
+            available_space INTEGER NOT NULL,
+            total_space INTEGER NOT NULL,
+            PRIMARY KEY (timestamp, mount_point)
+        );
+
+        CREATE TABLE IF NOT EXISTS CustomMetrics (
+            timestamp DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            name TEXT NOT NULL,
+            value REAL NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS Silences (
+            id TEXT PRIMARY KEY,
+            alert_ids TEXT NOT NULL,
+            metric_ids TEXT NOT NULL,
+            labels TEXT NOT NULL,
+            starts_at INTEGER,
+            ends_at INTEGER,
+            schedule TEXT,
+            duration INTEGER,
+            created_by TEXT NOT NULL,
+            comment TEXT NOT NULL,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+
+        CREATE TABLE IF NOT EXISTS Incidents (
+            id TEXT PRIMARY KEY,
+            alert_id TEXT NOT NULL,
+            started_at INTEGER NOT NULL,
+            resolved_at INTEGER,
+            acknowledged_at INTEGER,
+            acknowledged_by TEXT,
+            ack_note TEXT,
+            escalation_step INTEGER NOT NULL DEFAULT 0
+        );
+
+        CREATE TABLE IF NOT EXISTS IncidentEvents (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            incident_id TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            detail TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS Deliveries (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            alert_id TEXT NOT NULL,
+            target TEXT NOT NULL,
+            attempt INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL,
+            status INTEGER,
+            latency_ms INTEGER NOT NULL,
+            error TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS AlertEvents (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            alert_id TEXT NOT NULL,
+            incident_id TEXT NOT NULL,
+            state TEXT NOT NULL,
+            timestamp INTEGER NOT NULL,
+            severity TEXT,
+            metric_id TEXT,
+            value REAL
+        );
+
         CREATE INDEX IF NOT EXISTS idx_cpu_timestamp ON CpuMetrics(timestamp);
         CREATE INDEX IF NOT EXISTS idx_memory_timestamp ON MemoryMetrics(timestamp);
         CREATE INDEX IF NOT EXISTS idx_disk_timestamp ON DiskMetrics(timestamp);
+        CREATE INDEX IF NOT EXISTS idx_mount_timestamp ON MountMetrics(timestamp);
+        CREATE INDEX IF NOT EXISTS idx_custom_name_timestamp ON CustomMetrics(name, timestamp);
+        CREATE INDEX IF NOT EXISTS idx_incidents_alert ON Incidents(alert_id, resolved_at);
+        CREATE INDEX IF NOT EXISTS idx_incidents_started ON Incidents(started_at);
+        CREATE INDEX IF NOT EXISTS idx_incident_events_incident ON IncidentEvents(incident_id, timestamp);
+        CREATE INDEX IF NOT EXISTS idx_deliveries_timestamp ON Deliveries(timestamp);
+        CREATE INDEX IF NOT EXISTS idx_alert_events_alert ON AlertEvents(alert_id, timestamp);
+        CREATE INDEX IF NOT EXISTS idx_alert_events_timestamp ON AlertEvents(timestamp);
+        CREATE INDEX IF NOT EXISTS idx_alert_events_incident ON AlertEvents(incident_id);
         "#
 }
 
@@ -103,6 +188,7 @@ pub async fn insert_metrics(pool: &SqlitePool, metric: MetricType) -> Result<(),
         MetricType::Cpu(cpu_metrics) => insert_cpu_metrics(pool, cpu_metrics).await,
         MetricType::Memory(memory_metrics) => insert_memory_metrics(pool, memory_metrics).await,
         MetricType::Disk(disk_metrics) => insert_disk_metrics(pool, disk_metrics).await,
+        MetricType::Custom(custom_metric) => insert_custom_metric(pool, custom_metric).await,
     }
 }
 
@@ -158,15 +244,57 @@ async fn insert_disk_metrics(
     .map(|_| ())
 }
 
-pub async fn get_metric(pool: &SqlitePool, metric_type: MetricType) -> MetricType {
-    match metric_type {
-        MetricType::Cpu(_) => get_cpu_metric(pool).await.unwrap_or(metric_type),
-        MetricType::Memory(_) => get_memory_metric(pool).await.unwrap_or(metric_type),
-        MetricType::Disk(_) => get_disk_metric(pool).await.unwrap_or(metric_type),
-    }
+async fn insert_custom_metric(
+    pool: &SqlitePool,
+    custom_metric: CustomMetric,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+            INSERT INTO CustomMetrics (name, value)
+            VALUES (?, ?)
+            "#,
+    )
+    .bind(custom_metric.name)
+    .bind(custom_metric.value)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+pub async fn insert_mount_metrics(
+    pool: &SqlitePool,
+    mount_point: &str,
+    disk_metrics: DiskMetrics,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+            INSERT INTO MountMetrics (mount_point, total_space, available_space)
+            VALUES (?, ?, ?)
+            "#,
+    )
+    .bind(mount_point)
+    .bind(disk_metrics.total)
+    .bind(disk_metrics.free)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+// The newest row of a metric, `None` if it never reported (or the read failed)
+pub async fn get_metric(pool: &SqlitePool, metric_type: MetricType) -> Option<MetricType> {
+    let metric = match metric_type {
+        MetricType::Cpu(_) => get_cpu_metric(pool).await,
+        MetricType::Memory(_) => get_memory_metric(pool).await,
+        MetricType::Disk(_) => get_disk_metric(pool).await,
+        MetricType::Custom(custom) => get_custom_metric(pool, &custom.name).await,
+    };
+    metric.unwrap_or_else(|e| {
+        eprintln!("Failed to read last metric: {:?}", e);
+        None
+    })
 }
 
-async fn get_cpu_metric(pool: &SqlitePool) -> Result<MetricType, sqlx::Error> {
+async fn get_cpu_metric(pool: &SqlitePool) -> Result<Option<MetricType>, sqlx::Error> {
     let row = sqlx::query(
         r#"
             SELECT usage_percentage, load_average_1m, load_average_5m, load_average_15m
@@ -175,20 +303,13 @@ async fn get_cpu_metric(pool: &SqlitePool) -> Result<MetricType, sqlx::Error> {
             LIMIT 1
             "#,
     )
-    .fetch_one(pool)
+    .fetch_optional(pool)
     .await?;
 
-    Ok(MetricType::Cpu(CpuMetrics {
-        usage_percentage: row.get("usage_percentage"),
-        load_average: [
-            row.get("load_average_1m"),
-            row.get("load_average_5m"),
-            row.get("load_average_15m"),
-        ],
-    }))
+    Ok(row.as_ref().map(cpu_from_row))
 }
 
-async fn get_memory_metric(pool: &SqlitePool) -> Result<MetricType, sqlx::Error> {
+async fn get_memory_metric(pool: &SqlitePool) -> Result<Option<MetricType>, sqlx::Error> {
     let row = sqlx::query(
         r#"
             SELECT total_memory, used_memory, total_swap, used_swap
@@ -197,18 +318,13 @@ async fn get_memory_metric(pool: &SqlitePool) -> Result<MetricType, sqlx::Error>
             LIMIT 1
             "#,
     )
-    .fetch_one(pool)
+    .fetch_optional(pool)
     .await?;
 
-    Ok(MetricType::Memory(MemoryMetrics::new(
-        row.get::<i64, _>("total_memory") as u32,
-        row.get::<i64, _>("used_memory") as u32,
-        row.get::<i64, _>("total_swap") as u32,
-        row.get::<i64, _>("used_swap") as u32,
-    )))
+    Ok(row.as_ref().map(memory_from_row))
 }
 
-async fn get_disk_metric(pool: &SqlitePool) -> Result<MetricType, sqlx::Error> {
+async fn get_disk_metric(pool: &SqlitePool) -> Result<Option<MetricType>, sqlx::Error> {
     let row = sqlx::query(
         r#"
             SELECT available_space, total_space
@@ -217,12 +333,200 @@ async fn get_disk_metric(pool: &SqlitePool) -> Result<MetricType, sqlx::Error> {
             LIMIT 1
             "#,
     )
-    .fetch_one(pool)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.as_ref().map(disk_from_row))
+}
+
+async fn get_custom_metric(
+    pool: &SqlitePool,
+    name: &str,
+) -> Result<Option<MetricType>, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+            SELECT name, value
+            FROM CustomMetrics
+            WHERE name = ?
+            ORDER BY timestamp DESC
+            LIMIT 1
+            "#,
+    )
+    .bind(name)
+    .fetch_optional(pool)
     .await?;
-    Ok(MetricType::Disk(DiskMetrics::new(
+
+    Ok(row.as_ref().map(custom_from_row))
+}
+
+fn cpu_from_row(row: &SqliteRow) -> MetricType {
+    MetricType::Cpu(CpuMetrics {
+        usage_percentage: row.get("usage_percentage"),
+        load_average: [
+            row.get("load_average_1m"),
+            row.get("load_average_5m"),
+            row.get("load_average_15m"),
+        ],
+    })
+}
+
+fn memory_from_row(row: &SqliteRow) -> MetricType {
+    MetricType::Memory(MemoryMetrics::new(
+        row.get::<i64, _>("total_memory") as u32,
+        row.get::<i64, _>("used_memory") as u32,
+        row.get::<i64, _>("total_swap") as u32,
+        row.get::<i64, _>("used_swap") as u32,
+    ))
+}
+
+fn disk_from_row(row: &SqliteRow) -> MetricType {
+    MetricType::Disk(DiskMetrics::new(
         row.get::<i64, _>("total_space") as u32,
         row.get::<i64, _>("available_space") as u32,
-    )))
+    ))
+}
+
+fn custom_from_row(row: &SqliteRow) -> MetricType {
+    MetricType::Custom(CustomMetric::new(row.get("name"), row.get("value")))
+}
+
+// Table holding a metric type and, for custom metrics, the name rows are filtered on
+fn metric_table(metric_type: &MetricType) -> (&'static str, Option<&str>) {
+    match metric_type {
+        MetricType::Cpu(_) => ("CpuMetrics", None),
+        MetricType::Memory(_) => ("MemoryMetrics", None),
+        MetricType::Disk(_) => ("DiskMetrics", None),
+        MetricType::Custom(custom) => ("CustomMetrics", Some(custom.name.as_str())),
+    }
+}
+
+/// Returns `(unix timestamp, value)` pairs for a single metric field, oldest first.
+pub async fn get_metric_history(
+    pool: &SqlitePool,
+    metric_type: MetricType,
+    metric_id: &str,
+    since: i64,
+    until: i64,
+) -> Result<Vec<(i64, f32)>, sqlx::Error> {
+    let from_row: fn(&SqliteRow) -> MetricType = match metric_type {
+        MetricType::Cpu(_) => cpu_from_row,
+        MetricType::Memory(_) => memory_from_row,
+        MetricType::Disk(_) => disk_from_row,
+        MetricType::Custom(_) => custom_from_row,
+    };
+    let (table, name) = metric_table(&metric_type);
+    let name_filter = if name.is_some() { "name = ? AND" } else { "" };
+    let query = format!(
+        r#"
+            SELECT CAST(strftime('%s', timestamp) AS INTEGER) as unix_time, *
+            FROM {table}
+            WHERE {name_filter} timestamp >= datetime(?, 'unixepoch') AND timestamp <= datetime(?, 'unixepoch')
+            ORDER BY timestamp ASC
+            "#
+    );
+    let mut query = sqlx::query(query.as_str());
+    if let Some(name) = name {
+        query = query.bind(name);
+    }
+    let rows = query.bind(since).bind(until).fetch_all(pool).await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let value = from_row(row).get_value(metric_id.to_string());
+            (row.get::<i64, _>("unix_time"), value)
+        })
+        .collect())
+}
+
+/// Returns the unix timestamp of the newest row stored for a metric, if any.
+pub async fn get_last_timestamp(
+    pool: &SqlitePool,
+    metric_type: &MetricType,
+) -> Result<Option<i64>, sqlx::Error> {
+    let (table, name) = metric_table(metric_type);
+    let name_filter = if name.is_some() { "WHERE name = ?" } else { "" };
+    let query = format!(
+        r#"
+            SELECT CAST(strftime('%s', MAX(timestamp)) AS INTEGER) as unix_time
+            FROM {table}
+            {name_filter}
+            "#
+    );
+    let mut query = sqlx::query(query.as_str());
+    if let Some(name) = name {
+        query = query.bind(name);
+    }
+    let row = query.fetch_one(pool).await?;
+    Ok(row.get::<Option<i64>, _>("unix_time"))
+}
+
+// Newest timestamp of every custom metric name still within the retention period
+pub async fn get_custom_last_timestamps(
+    pool: &SqlitePool,
+) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT name, CAST(strftime('%s', MAX(timestamp)) AS INTEGER) as unix_time
+            FROM CustomMetrics
+            GROUP BY name
+            ORDER BY name ASC
+            "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| (row.get("name"), row.get("unix_time")))
+        .collect())
+}
+
+pub async fn get_mount_points(pool: &SqlitePool, since: i64) -> Result<Vec<String>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT DISTINCT mount_point
+            FROM MountMetrics
+            WHERE timestamp >= datetime(?, 'unixepoch')
+            ORDER BY mount_point ASC
+            "#,
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(|row| row.get("mount_point")).collect())
+}
+
+/// Returns `(unix timestamp, metrics)` pairs for a single mount point, oldest first.
+pub async fn get_mount_history(
+    pool: &SqlitePool,
+    mount_point: &str,
+    since: i64,
+) -> Result<Vec<(i64, DiskMetrics)>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT CAST(strftime('%s', timestamp) AS INTEGER) as unix_time, available_space, total_space
+            FROM MountMetrics
+            WHERE mount_point = ? AND timestamp >= datetime(?, 'unixepoch')
+            ORDER BY timestamp ASC
+            "#,
+    )
+    .bind(mount_point)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let metrics = DiskMetrics::new(
+                row.get::<i64, _>("total_space") as u32,
+                row.get::<i64, _>("available_space") as u32,
+            );
+            (row.get::<i64, _>("unix_time"), metrics)
+        })
+        .collect())
 }
 
 pub async fn get_cpu_average_since(pool: &SqlitePool, timestamp: i64) -> Result<f32, sqlx::Error> {
@@ -313,15 +617,641 @@ pub async fn get_historical_memory_metrics(
     Ok(metrics)
 }
 
+// Inserts a new silence or replaces the one with the same id
+pub async fn save_silence(pool: &SqlitePool, silence: &Silence) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+            INSERT OR REPLACE INTO Silences
+                (id, alert_ids, metric_ids, labels, starts_at, ends_at, schedule, duration, created_by, comment)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+    )
+    .bind(silence.id.to_string())
+    .bind(serde_json::to_string(&silence.alert_ids).unwrap_or_default())
+    .bind(serde_json::to_string(&silence.metric_ids).unwrap_or_default())
+    .bind(serde_json::to_string(&silence.labels).unwrap_or_default())
+    .bind(silence.starts_at)
+    .bind(silence.ends_at)
+    .bind(&silence.schedule)
+    .bind(silence.duration.map(|d| d as i64))
+    .bind(&silence.created_by)
+    .bind(&silence.comment)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+pub async fn delete_silence(pool: &SqlitePool, id: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query("DELETE FROM Silences WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+}
+
+pub async fn get_silences(pool: &SqlitePool) -> Result<Vec<Silence>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT id, alert_ids, metric_ids, labels, starts_at, ends_at, schedule, duration, created_by, comment
+            FROM Silences
+            ORDER BY created_at ASC
+            "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| Silence {
+            id: row.get::<String, _>("id").parse().unwrap_or_default(),
+            alert_ids: serde_json::from_str(row.get("alert_ids")).unwrap_or_default(),
+            metric_ids: serde_json::from_str(row.get("metric_ids")).unwrap_or_default(),
+            labels: serde_json::from_str(row.get("labels")).unwrap_or_default(),
+            starts_at: row.get("starts_at"),
+            ends_at: row.get("ends_at"),
+            schedule: row.get("schedule"),
+            duration: row.get::<Option<i64>, _>("duration").map(|d| d as u64),
+            created_by: row.get("created_by"),
+            comment: row.get("comment"),
+        })
+        .collect())
+}
+
+pub async fn open_incident(
+    pool: &SqlitePool,
+    alert_id: Uuid,
+    started_at: i64,
+) -> Result<Incident, sqlx::Error> {
+    let incident = Incident {
+        id: Uuid::new_v4(),
+        alert_id,
+        started_at,
+        ..Default::default()
+    };
+    sqlx::query(
+        r#"
+            INSERT INTO Incidents (id, alert_id, started_at, escalation_step)
+            VALUES (?, ?, ?, 0)
+            "#,
+    )
+    .bind(incident.id.to_string())
+    .bind(incident.alert_id.to_string())
+    .bind(incident.started_at)
+    .execute(pool)
+    .await?;
+    Ok(incident)
+}
+
+pub async fn get_open_incidents(pool: &SqlitePool) -> Result<Vec<Incident>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT id, alert_id, started_at, resolved_at, acknowledged_at, acknowledged_by, ack_note,
+                escalation_step
+            FROM Incidents
+            WHERE resolved_at IS NULL
+            "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(incident_from_row).collect())
+}
+
+// Newest first, optionally only the ones still firing
+pub async fn get_incidents(
+    pool: &SqlitePool,
+    limit: u32,
+    open_only: bool,
+) -> Result<Vec<Incident>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT id, alert_id, started_at, resolved_at, acknowledged_at, acknowledged_by, ack_note,
+                escalation_step
+            FROM Incidents
+            WHERE (? = 0 OR resolved_at IS NULL)
+            ORDER BY started_at DESC
+            LIMIT ?
+            "#,
+    )
+    .bind(open_only)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(incident_from_row).collect())
+}
+
+pub async fn get_incident(pool: &SqlitePool, id: &str) -> Result<Option<Incident>, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+            SELECT id, alert_id, started_at, resolved_at, acknowledged_at, acknowledged_by, ack_note,
+                escalation_step
+            FROM Incidents
+            WHERE id = ?
+            "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.as_ref().map(incident_from_row))
+}
+
+pub async fn add_incident_event(
+    pool: &SqlitePool,
+    incident_id: Uuid,
+    event: &IncidentEvent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+            INSERT INTO IncidentEvents (incident_id, timestamp, kind, detail)
+            VALUES (?, ?, ?, ?)
+            "#,
+    )
+    .bind(incident_id.to_string())
+    .bind(event.timestamp)
+    .bind(event.kind.to_string())
+    .bind(&event.detail)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+pub async fn get_incident_events(
+    pool: &SqlitePool,
+    incident_id: Uuid,
+) -> Result<Vec<IncidentEvent>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT timestamp, kind, detail
+            FROM IncidentEvents
+            WHERE incident_id = ?
+            ORDER BY timestamp ASC, id ASC
+            "#,
+    )
+    .bind(incident_id.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            Some(IncidentEvent {
+                timestamp: row.get("timestamp"),
+                kind: row.get::<String, _>("kind").parse().ok()?,
+                detail: row.get("detail"),
+            })
+        })
+        .collect())
+}
+
+pub async fn set_escalation_step(
+    pool: &SqlitePool,
+    id: Uuid,
+    escalation_step: u32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE Incidents SET escalation_step = ? WHERE id = ?")
+        .bind(escalation_step as i64)
+        .bind(id.to_string())
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+// Acknowledges the open incident of an alert, `None` if nothing was waiting for an ack
+pub async fn acknowledge_incident(
+    pool: &SqlitePool,
+    alert_id: &str,
+    acknowledged_at: i64,
+    acknowledged_by: Option<&str>,
+    ack_note: Option<&str>,
+) -> Result<Option<Incident>, sqlx::Error> {
+    let row = sqlx::query(
+        r#"
+            UPDATE Incidents SET acknowledged_at = ?, acknowledged_by = ?, ack_note = ?
+            WHERE alert_id = ? AND resolved_at IS NULL AND acknowledged_at IS NULL
+            RETURNING id, alert_id, started_at, resolved_at, acknowledged_at, acknowledged_by, ack_note,
+                escalation_step
+            "#,
+    )
+    .bind(acknowledged_at)
+    .bind(acknowledged_by)
+    .bind(ack_note)
+    .bind(alert_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.as_ref().map(incident_from_row))
+}
+
+pub async fn resolve_incident(
+    pool: &SqlitePool,
+    id: Uuid,
+    resolved_at: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE Incidents SET resolved_at = ? WHERE id = ?")
+        .bind(resolved_at)
+        .bind(id.to_string())
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+fn incident_from_row(row: &SqliteRow) -> Incident {
+    Incident {
+        id: row.get::<String, _>("id").parse().unwrap_or_default(),
+        alert_id: row.get::<String, _>("alert_id").parse().unwrap_or_default(),
+        started_at: row.get("started_at"),
+        resolved_at: row.get("resolved_at"),
+        acknowledged_at: row.get("acknowledged_at"),
+        acknowledged_by: row.get("acknowledged_by"),
+        ack_note: row.get("ack_note"),
+        escalation_step: row.get::<i64, _>("escalation_step") as u32,
+        events: vec![],
+    }
+}
+
+pub async fn insert_delivery(pool: &SqlitePool, delivery: &Delivery) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+            INSERT INTO Deliveries (alert_id, target, attempt, timestamp, status, latency_ms, error)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+    )
+    .bind(delivery.alert_id.to_string())
+    .bind(&delivery.target)
+    .bind(delivery.attempt)
+    .bind(delivery.timestamp)
+    .bind(delivery.status)
+    .bind(delivery.latency_ms as i64)
+    .bind(&delivery.error)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+// Newest first, optionally only the attempts that failed
+pub async fn get_deliveries(
+    pool: &SqlitePool,
+    limit: u32,
+    failed_only: bool,
+) -> Result<Vec<Delivery>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT alert_id, target, attempt, timestamp, status, latency_ms, error
+            FROM Deliveries
+            WHERE (? = 0 OR error IS NOT NULL)
+            ORDER BY timestamp DESC, id DESC
+            LIMIT ?
+            "#,
+    )
+    .bind(failed_only)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| Delivery {
+            alert_id: row.get::<String, _>("alert_id").parse().unwrap_or_default(),
+            target: row.get("target"),
+            attempt: row.get::<i64, _>("attempt") as u32,
+            timestamp: row.get("timestamp"),
+            status: row.get::<Option<i64>, _>("status").map(|s| s as u16),
+            latency_ms: row.get::<i64, _>("latency_ms") as u64,
+            error: row.get("error"),
+        })
+        .collect())
+}
+
+pub async fn insert_alert_event(pool: &SqlitePool, event: &AlertEvent) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+            INSERT INTO AlertEvents (alert_id, incident_id, state, timestamp, severity, metric_id, value)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+    )
+    .bind(event.alert_id.to_string())
+    .bind(event.incident_id.to_string())
+    .bind(event.state.to_string())
+    .bind(event.timestamp)
+    .bind(&event.severity)
+    .bind(&event.metric_id)
+    .bind(event.value)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+// Newest first
+pub async fn get_alert_events(
+    pool: &SqlitePool,
+    filter: &HistoryFilter,
+    limit: u32,
+) -> Result<Vec<AlertEvent>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT alert_id, incident_id, state, timestamp, severity, metric_id, value
+            FROM AlertEvents
+            WHERE (? IS NULL OR alert_id = ?)
+                AND (? IS NULL OR metric_id = ?)
+                AND (? IS NULL OR timestamp >= ?)
+                AND (? IS NULL OR timestamp <= ?)
+                AND (? IS NULL OR state = ?)
+            ORDER BY timestamp DESC, id DESC
+            LIMIT ?
+            "#,
+    )
+    .bind(filter.alert_id.map(|id| id.to_string()))
+    .bind(filter.alert_id.map(|id| id.to_string()))
+    .bind(&filter.metric_id)
+    .bind(&filter.metric_id)
+    .bind(filter.since)
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(filter.until)
+    .bind(filter.state.map(|s| s.to_string()))
+    .bind(filter.state.map(|s| s.to_string()))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| AlertEvent {
+            alert_id: row.get::<String, _>("alert_id").parse().unwrap_or_default(),
+            incident_id: row
+                .get::<String, _>("incident_id")
+                .parse()
+                .unwrap_or_default(),
+            state: match row.get::<String, _>("state").as_str() {
+                "resolved" => AlertState::Resolved,
+                _ => AlertState::Firing,
+            },
+            timestamp: row.get("timestamp"),
+            severity: row.get("severity"),
+            metric_id: row.get("metric_id"),
+            value: row.get::<Option<f64>, _>("value").map(|v| v as f32),
+        })
+        .collect())
+}
+
+// How often each alert started firing per local day. `since`/`until` select by firing time,
+// the state filter does not apply.
+pub async fn count_firings_per_day(
+    pool: &SqlitePool,
+    filter: &HistoryFilter,
+) -> Result<Vec<DailyCount>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT alert_id, date(timestamp, 'unixepoch', 'localtime') AS day, COUNT(*) AS count
+            FROM AlertEvents
+            WHERE state = 'firing'
+                AND (? IS NULL OR alert_id = ?)
+                AND (? IS NULL OR metric_id = ?)
+                AND (? IS NULL OR timestamp >= ?)
+                AND (? IS NULL OR timestamp <= ?)
+            GROUP BY alert_id, day
+            ORDER BY day DESC, alert_id
+            "#,
+    )
+    .bind(filter.alert_id.map(|id| id.to_string()))
+    .bind(filter.alert_id.map(|id| id.to_string()))
+    .bind(&filter.metric_id)
+    .bind(&filter.metric_id)
+    .bind(filter.since)
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(filter.until)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| DailyCount {
+            alert_id: row.get::<String, _>("alert_id").parse().unwrap_or_default(),
+            day: row.get("day"),
+            count: row.get::<i64, _>("count") as u32,
+        })
+        .collect())
+}
+
+// Pairs the firing and resolved event of every incident resolved in the range, so `since`/`until`
+// select by resolution time and an incident that fired before `since` still counts. The state
+// filter does not apply. The metric is matched on the firing event, deleted alerts resolve
+// without one.
+pub async fn mean_time_to_resolve(
+    pool: &SqlitePool,
+    filter: &HistoryFilter,
+) -> Result<Vec<ResolveTime>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+            SELECT r.alert_id, COUNT(*) AS resolved, AVG(r.timestamp - f.timestamp) AS mean_seconds
+            FROM AlertEvents r
+            JOIN AlertEvents f ON f.incident_id = r.incident_id AND f.state = 'firing'
+            WHERE r.state = 'resolved'
+                AND (? IS NULL OR r.alert_id = ?)
+                AND (? IS NULL OR f.metric_id = ?)
+                AND (? IS NULL OR r.timestamp >= ?)
+                AND (? IS NULL OR r.timestamp <= ?)
+            GROUP BY r.alert_id
+            ORDER BY r.alert_id
+            "#,
+    )
+    .bind(filter.alert_id.map(|id| id.to_string()))
+    .bind(filter.alert_id.map(|id| id.to_string()))
+    .bind(&filter.metric_id)
+    .bind(&filter.metric_id)
+    .bind(filter.since)
+    .bind(filter.since)
+    .bind(filter.until)
+    .bind(filter.until)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| ResolveTime {
+            alert_id: row.get::<String, _>("alert_id").parse().unwrap_or_default(),
+            resolved: row.get::<i64, _>("resolved") as u32,
+            mean_seconds: row.get("mean_seconds"),
+        })
+        .collect())
+}
+
 pub async fn cleanup_metrics(pool: &SqlitePool, retention_period: u32) {
     let query = format!(
         r#"
         DELETE FROM CpuMetrics WHERE strftime('%s', timestamp) < strftime('%s', 'now', '-{retention_period} day');
         DELETE FROM MemoryMetrics WHERE strftime('%s', timestamp) < strftime('%s', 'now', '-{retention_period} day');
         DELETE FROM DiskMetrics WHERE strftime('%s', timestamp) < strftime('%s', 'now', '-{retention_period} day');
+        DELETE FROM MountMetrics WHERE strftime('%s', timestamp) < strftime('%s', 'now', '-{retention_period} day');
+        DELETE FROM CustomMetrics WHERE strftime('%s', timestamp) < strftime('%s', 'now', '-{retention_period} day');
+        DELETE FROM Deliveries WHERE timestamp < CAST(strftime('%s', 'now', '-{retention_period} day') AS INTEGER);
+        DELETE FROM AlertEvents WHERE timestamp < CAST(strftime('%s', 'now', '-{retention_period} day') AS INTEGER);
+        DELETE FROM IncidentEvents WHERE incident_id IN (
+            SELECT id FROM Incidents WHERE resolved_at < CAST(strftime('%s', 'now', '-{retention_period} day') AS INTEGER)
+        );
+        DELETE FROM Incidents WHERE resolved_at < CAST(strftime('%s', 'now', '-{retention_period} day') AS INTEGER);
         "#
     );
     if let Err(e) = sqlx::query(query.as_str()).execute(pool).await {
         eprintln!("Error cleaning up old metrics: {:?}", e);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::models::incident::EventKind;
+
+    #[tokio::test]
+    async fn cleanup_removes_old_resolved_incidents_and_their_events() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let pool = Arc::new(pool);
+        init_db(&pool, 1).await;
+
+        let now = chrono::Utc::now().timestamp();
+        let two_days_ago = now - 2 * 86400;
+        let old_resolved = open_incident(&pool, Uuid::new_v4(), two_days_ago)
+            .await
+            .unwrap();
+        resolve_incident(&pool, old_resolved.id, two_days_ago + 60)
+            .await
+            .unwrap();
+        let old_open = open_incident(&pool, Uuid::new_v4(), two_days_ago)
+            .await
+            .unwrap();
+        let recent = open_incident(&pool, Uuid::new_v4(), two_days_ago)
+            .await
+            .unwrap();
+        resolve_incident(&pool, recent.id, now - 60).await.unwrap();
+        for incident in [&old_resolved, &old_open, &recent] {
+            let event = IncidentEvent {
+                timestamp: two_days_ago,
+                kind: EventKind::Fired,
+                detail: String::new(),
+            };
+            add_incident_event(&pool, incident.id, &event)
+                .await
+                .unwrap();
+        }
+
+        cleanup_metrics(&pool, 1).await;
+
+        assert!(
+            get_incident(&pool, &old_resolved.id.to_string())
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            get_incident_events(&pool, old_resolved.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        for incident in [&old_open, &recent] {
+            assert!(
+                get_incident(&pool, &incident.id.to_string())
+                    .await
+                    .unwrap()
+                    .is_some()
+            );
+            assert_eq!(
+                get_incident_events(&pool, incident.id).await.unwrap().len(),
+                1
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn history_aggregations_select_by_firing_and_resolution_time() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let pool = Arc::new(pool);
+        init_db(&pool, 1).await;
+
+        // Recent enough for the cleanup that init_db starts
+        let since = chrono::Utc::now().timestamp() - 3600;
+        let (cpu, disk) = (Uuid::new_v4(), Uuid::new_v4());
+        let incidents = [
+            // Fired before the range, resolved within it
+            (cpu, "cpu_usage_percentage", since - 100, Some(since + 100)),
+            (cpu, "cpu_usage_percentage", since + 200, Some(since + 260)),
+            (disk, "disk_usage_percentage", since + 300, None),
+        ];
+        for (alert_id, metric_id, fired, resolved) in incidents {
+            let incident_id = Uuid::new_v4();
+            let event = |state, timestamp| AlertEvent {
+                alert_id,
+                incident_id,
+                state,
+                timestamp,
+                metric_id: Some(metric_id.to_string()),
+                ..Default::default()
+            };
+            insert_alert_event(&pool, &event(AlertState::Firing, fired))
+                .await
+                .unwrap();
+            if let Some(resolved) = resolved {
+                insert_alert_event(&pool, &event(AlertState::Resolved, resolved))
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let filter = HistoryFilter {
+            since: Some(since),
+            state: Some(AlertState::Resolved),
+            ..Default::default()
+        };
+        let events = get_alert_events(&pool, &filter, 100).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.state == AlertState::Resolved));
+
+        let mut daily: Vec<(Uuid, u32)> = count_firings_per_day(&pool, &filter)
+            .await
+            .unwrap()
+            .iter()
+            .map(|d| (d.alert_id, d.count))
+            .collect();
+        daily.sort();
+        let mut expected = vec![(cpu, 1), (disk, 1)];
+        expected.sort();
+        assert_eq!(daily, expected);
+
+        let resolve_times = mean_time_to_resolve(&pool, &filter).await.unwrap();
+        assert_eq!(
+            resolve_times,
+            [ResolveTime {
+                alert_id: cpu,
+                resolved: 2,
+                mean_seconds: 130.0,
+            }]
+        );
+
+        let disk_only = HistoryFilter {
+            metric_id: Some("disk_usage_percentage".to_string()),
+            ..filter
+        };
+        let daily = count_firings_per_day(&pool, &disk_only).await.unwrap();
+        assert_eq!(daily.len(), 1);
+        assert_eq!(daily[0].alert_id, disk);
+        assert!(
+            mean_time_to_resolve(&pool, &disk_only)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
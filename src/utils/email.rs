@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use lettre::{
+    AsyncSmtpTransport, Message, Tokio1Executor,
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+};
+use minijinja::{Value, context};
+
+use crate::models::channel::{Email, SmtpTls};
+
+use super::template;
+
+const DEFAULT_SUBJECT: &str = "{% if alerts | length == 1 %}[{{ alerts[0].severity }}] Alert \
+{{ alerts[0].state }}: {{ alerts[0].condition }}{% else %}{{ alerts | length }} alerts on \
+{{ hostname }}{% endif %}";
+
+const DEFAULT_TEXT: &str = "{% for alert in alerts %}[{{ alert.severity }}] {{ alert.state }}: \
+{{ alert.condition }}
+{% if alert.value is not none %}Value: {{ alert.metric_id }} = {{ alert.value | number(2) }}
+{% endif %}{% if alert.threshold is not none %}Threshold: {{ alert.logic }} {{ alert.threshold }}
+{% endif %}Host: {{ alert.hostname }}
+Time: {{ alert.time }}
+
+{% endfor %}{% if dashboard_url %}Dashboard: {{ dashboard_url }}
+{% endif %}";
+
+const DEFAULT_HTML: &str = "<html><body style=\"font-family: sans-serif\">\
+{% for alert in alerts %}<h3 style=\"color: {% if alert.state == 'resolved' %}#2eb67d\
+{% elif alert.severity == 'critical' %}#d00000{% else %}#b8860b{% endif %}\">\
+[{{ alert.severity }}] Alert {{ alert.state }}</h3><table>\
+<tr><td>Condition</td><td><code>{{ alert.condition }}</code></td></tr>\
+{% if alert.value is not none %}<tr><td>Value</td><td>{{ alert.metric_id }} = \
+{{ alert.value | number(2) }}</td></tr>{% endif %}\
+{% if alert.threshold is not none %}<tr><td>Threshold</td><td>{{ alert.logic }} \
+{{ alert.threshold }}</td></tr>{% endif %}\
+<tr><td>Host</td><td>{{ alert.hostname }}</td></tr>\
+<tr><td>Time</td><td>{{ alert.time }}</td></tr></table>{% endfor %}\
+{% if dashboard_url %}<p><a href=\"{{ dashboard_url }}\">Open dashboard</a></p>{% endif %}\
+</body></html>";
+
+// One multipart message for every alert collected during a check
+pub fn build_message(
+    email: &Email,
+    alerts: Vec<Value>,
+    dashboard_url: Option<&str>,
+) -> Result<Message, String> {
+    let context = context! {
+        alerts => alerts,
+        hostname => sysinfo::System::host_name().unwrap_or_default(),
+        dashboard_url => dashboard_url,
+    };
+    let subject = template::render_context(
+        email.subject.as_deref().unwrap_or(DEFAULT_SUBJECT),
+        context.clone(),
+        false,
+    )?;
+    let text = template::render_context(
+        email.text.as_deref().unwrap_or(DEFAULT_TEXT),
+        context.clone(),
+        false,
+    )?;
+    let html =
+        template::render_context(email.html.as_deref().unwrap_or(DEFAULT_HTML), context, true)?;
+
+    let mut builder = Message::builder()
+        .from(parse_mailbox(&email.from)?)
+        .subject(subject.trim());
+    for to in &email.to {
+        builder = builder.to(parse_mailbox(to)?);
+    }
+    builder
+        .multipart(MultiPart::alternative_plain_html(text, html))
+        .map_err(|e| e.to_string())
+}
+
+pub fn build_transport(
+    email: &Email,
+    timeout: Duration,
+) -> Result<AsyncSmtpTransport<Tokio1Executor>, String> {
+    let builder = match email.tls {
+        SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&email.host),
+        SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&email.host),
+        SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+            &email.host,
+        )),
+    }
+    .map_err(|e| e.to_string())?;
+    let mut builder = builder.port(email.port).timeout(Some(timeout));
+    if let (Some(username), Some(password)) = (&email.username, &email.password) {
+        builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    Ok(builder.build())
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, String> {
+    address
+        .parse()
+        .map_err(|e| format!("Invalid email address {}: {}", address, e))
+}
@@ -0,0 +1,242 @@
+use std::{process::Stdio, time::Duration};
+
+use tokio::process::Command;
+
+use crate::models::{
+    alert::{Alert, Observation},
+    exec::Exec,
+    incident::AlertState,
+};
+
+use super::template;
+
+// Output beyond this is cut off before it reaches the alert log
+const MAX_OUTPUT: usize = 4096;
+
+pub struct Execution {
+    pub status: Option<i32>,
+    pub output: String,
+}
+
+// Spawn failures and timeouts are errors, a non-zero exit is reported in `status`
+pub async fn run(
+    exec: &Exec,
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+) -> Result<Execution, String> {
+    let args: Vec<String> = exec
+        .args
+        .iter()
+        .map(|arg| template::render(arg, alert, observations, state))
+        .collect();
+    let mut command = Command::new(&exec.command);
+    command
+        .args(args)
+        .envs(environment(alert, observations, state))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(user) = &exec.run_as {
+        let (uid, gid) = lookup_user(user)?;
+        command.uid(uid).gid(gid);
+    }
+    let child = command
+        .spawn()
+        .map_err(|e| format!("Failed to start {}: {}", exec.command, e))?;
+    let output =
+        match tokio::time::timeout(Duration::from_secs(exec.timeout), child.wait_with_output())
+            .await
+        {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => return Err(format!("Failed to wait for {}: {}", exec.command, e)),
+            Err(_) => return Err(format!("Timed out after {}s", exec.timeout)),
+        };
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    if text.len() > MAX_OUTPUT {
+        let mut end = MAX_OUTPUT;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("...");
+    }
+    Ok(Execution {
+        status: output.status.code(),
+        output: text,
+    })
+}
+
+// `SCIIGILO_LABEL_<KEY>` and `SCIIGILO_METRIC_<ID>` are upper-cased, like the other variables
+fn environment(
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+) -> Vec<(String, String)> {
+    let condition = alert.condition();
+    let mut env = vec![
+        ("SCIIGILO_ALERT_ID".to_string(), alert.id.to_string()),
+        ("SCIIGILO_SEVERITY".to_string(), alert.severity.to_string()),
+        ("SCIIGILO_STATE".to_string(), state.to_string()),
+        ("SCIIGILO_CONDITION".to_string(), condition.to_string()),
+        (
+            "SCIIGILO_HOSTNAME".to_string(),
+            sysinfo::System::host_name().unwrap_or_default(),
+        ),
+    ];
+    if let Some(observation) = observations.first() {
+        env.push((
+            "SCIIGILO_METRIC_ID".to_string(),
+            observation.metric_id.clone(),
+        ));
+        env.push(("SCIIGILO_VALUE".to_string(), observation.value.to_string()));
+    }
+    if let Some((_, threshold)) = condition.limit() {
+        env.push(("SCIIGILO_THRESHOLD".to_string(), threshold.to_string()));
+    }
+    for observation in observations {
+        env.push((
+            format!("SCIIGILO_METRIC_{}", env_name(&observation.metric_id)),
+            observation.value.to_string(),
+        ));
+    }
+    for (key, value) in &alert.labels {
+        env.push((format!("SCIIGILO_LABEL_{}", env_name(key)), value.clone()));
+    }
+    env
+}
+
+fn env_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect()
+}
+
+// Accepts a user name or a numeric uid, the gid is the primary group from /etc/passwd
+fn lookup_user(user: &str) -> Result<(u32, u32), String> {
+    let passwd = std::fs::read_to_string("/etc/passwd")
+        .map_err(|e| format!("Failed to read /etc/passwd: {}", e))?;
+    find_user(&passwd, user).ok_or_else(|| format!("Unknown user {}", user))
+}
+
+fn find_user(passwd: &str, user: &str) -> Option<(u32, u32)> {
+    let field = match user.parse::<u32>() {
+        Ok(_) => 2,
+        Err(_) => 0,
+    };
+    passwd
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.get(field) == Some(&user))
+        .and_then(|fields| Some((fields.get(2)?.parse().ok()?, fields.get(3)?.parse().ok()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::utils::notifier::tests::alert;
+
+    fn command(command: &str, args: &[&str], timeout: u64) -> Exec {
+        Exec {
+            command: command.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            timeout,
+            run_as: None,
+        }
+    }
+
+    fn observations() -> Vec<Observation> {
+        vec![Observation {
+            metric_id: "cpu_usage_percentage".to_string(),
+            value: 97.5,
+            baseline: None,
+        }]
+    }
+
+    #[test]
+    fn numeric_users_get_their_primary_group() {
+        let passwd = "root:x:0:0:root:/root:/bin/sh\nci:x:1001:118::/home/ci:/bin/sh\n";
+        assert_eq!(find_user(passwd, "ci"), Some((1001, 118)));
+        assert_eq!(find_user(passwd, "1001"), Some((1001, 118)));
+        assert_eq!(find_user(passwd, "0"), Some((0, 0)));
+        assert_eq!(find_user(passwd, "1002"), None);
+        assert_eq!(find_user(passwd, "x"), None);
+    }
+
+    #[tokio::test]
+    async fn arguments_are_not_interpreted_by_a_shell() {
+        let exec = command("echo", &["{{ metric_id }}; echo $(id) `id` > /tmp/x"], 5);
+        let alert = alert("cpu_usage_percentage", "90");
+        let execution = run(&exec, &alert, &observations(), AlertState::Firing)
+            .await
+            .unwrap();
+        assert_eq!(execution.status, Some(0));
+        assert_eq!(
+            execution.output,
+            "cpu_usage_percentage; echo $(id) `id` > /tmp/x\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn alert_is_described_in_the_environment() {
+        let mut alert = alert("cpu_usage_percentage", "90");
+        alert.labels = BTreeMap::from([("team-name".to_string(), "ops".to_string())]);
+        let exec = command(
+            "printenv",
+            &[
+                "SCIIGILO_STATE",
+                "SCIIGILO_CONDITION",
+                "SCIIGILO_VALUE",
+                "SCIIGILO_THRESHOLD",
+                "SCIIGILO_METRIC_CPU_USAGE_PERCENTAGE",
+                "SCIIGILO_LABEL_TEAM_NAME",
+            ],
+            5,
+        );
+        let execution = run(&exec, &alert, &observations(), AlertState::Resolved)
+            .await
+            .unwrap();
+        assert_eq!(
+            execution.output.lines().collect::<Vec<_>>(),
+            [
+                "resolved",
+                "cpu_usage_percentage > 90",
+                "97.5",
+                "90",
+                "97.5",
+                "ops"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn output_is_cut_off() {
+        let exec = command("sh", &["-c", "yes | head -c 5000"], 5);
+        let alert = alert("cpu_usage_percentage", "90");
+        let execution = run(&exec, &alert, &[], AlertState::Firing).await.unwrap();
+        assert_eq!(execution.output.len(), MAX_OUTPUT + 3);
+        assert!(execution.output.ends_with("y\n..."));
+    }
+
+    #[tokio::test]
+    async fn slow_commands_are_killed() {
+        let marker = std::env::temp_dir().join(format!("sciigilo-exec-{}", uuid::Uuid::new_v4()));
+        let script = format!("sleep 2; touch {}", marker.display());
+        let exec = command("sh", &["-c", &script], 1);
+        let alert = alert("cpu_usage_percentage", "90");
+        let error = run(&exec, &alert, &[], AlertState::Firing)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error, "Timed out after 1s");
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(!marker.exists(), "the command kept running");
+    }
+}
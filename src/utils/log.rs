@@ -1,50 +1,395 @@
-use std::io::BufRead;
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    sync::{Arc, Mutex},
+};
 
-use chrono::Local;
+use chrono::{Local, NaiveDateTime, TimeZone};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
 
-use crate::models::alert::Alert;
+use crate::models::{
+    alert::{Alert, Observation},
+    delivery::Delivery,
+    incident::AlertState,
+    log::{LogEntry, LogFilter},
+    silence::Silence,
+};
 
+use super::{config::EnvConfig, syslog::Syslog};
+
+// Writes the alert log as JSON lines and rotates it into `<file>.1.gz`, `<file>.2.gz`, ...
 #[derive(Clone)]
 pub struct Logger {
     file_path: String,
+    max_size: u64,
+    max_age: i64,
+    keep: u32,
+    // Held while writing, so clones rotate only once
+    lock: Arc<Mutex<()>>,
+    syslog: Option<Syslog>,
+    // Skips the log file when syslog is the only sink
+    syslog_only: bool,
 }
 
 impl Logger {
-    pub fn new(file_path: &str) -> Result<Logger, std::io::Error> {
+    pub fn new(env: &EnvConfig) -> Result<Logger, std::io::Error> {
+        let file_path = &env.log_file_path;
         if !std::path::Path::new(file_path).exists() {
-            std::fs::File::create(file_path)?;
+            File::create(file_path)?;
         }
         Ok(Logger {
             file_path: file_path.to_string(),
+            max_size: env.log_max_size,
+            max_age: env.log_max_age as i64 * 86400,
+            keep: env.log_keep,
+            lock: Arc::new(Mutex::new(())),
+            syslog: None,
+            syslog_only: false,
+        })
+    }
+
+    pub fn with_syslog(mut self, syslog: Syslog, syslog_only: bool) -> Logger {
+        self.syslog = Some(syslog);
+        self.syslog_only = syslog_only;
+        self
+    }
+
+    pub fn log(
+        &mut self,
+        alert: Alert,
+        observations: &[Observation],
+    ) -> Result<(), std::io::Error> {
+        self.emit("Notified", &alert, observations, &alert.to_string())
+    }
+
+    // Timeline entries such as escalations, acknowledgements and resolutions
+    pub fn log_event(
+        &mut self,
+        event: &str,
+        alert: &Alert,
+        observations: &[Observation],
+    ) -> Result<(), std::io::Error> {
+        self.emit(event, alert, observations, &format!("{}: {}", event, alert))
+    }
+
+    pub fn log_suppressed(
+        &mut self,
+        alert: Alert,
+        observations: &[Observation],
+        silence: &Silence,
+    ) -> Result<(), std::io::Error> {
+        let line = format!("Suppressed by {}: {}", silence, alert);
+        self.emit("Suppressed", &alert, observations, &line)
+    }
+
+    // The result line is followed by the captured output, indented one line at a time
+    pub fn log_command(
+        &mut self,
+        alert: &Alert,
+        observations: &[Observation],
+        result: &str,
+        output: &str,
+    ) -> Result<(), std::io::Error> {
+        let mut lines = vec![format!("Command {}: {}", result, alert)];
+        lines.extend(
+            output
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .map(|l| format!("    {}", l)),
+        );
+        self.emit("Command", alert, observations, &lines.join("\n"))
+    }
+
+    // The final attempt of a notification, only kept in the log file
+    pub fn log_delivery(&mut self, delivery: &Delivery) -> Result<(), std::io::Error> {
+        if self.syslog_only {
+            return Ok(());
+        }
+        let message = match &delivery.error {
+            Some(error) => format!(
+                "Delivery to {} failed after {} attempts: {}",
+                delivery.target, delivery.attempt, error
+            ),
+            None => format!(
+                "Delivered to {} after {} attempts",
+                delivery.target, delivery.attempt
+            ),
+        };
+        self.write_entry(LogEntry {
+            timestamp: delivery.timestamp,
+            event: "delivery".to_string(),
+            alert_id: Some(delivery.alert_id),
+            message,
+            delivery: Some(delivery.clone()),
+            ..Default::default()
         })
     }
 
-    pub fn log(&mut self, alert: Alert) -> Result<(), std::io::Error> {
-        let time = Local::now().format("%Y-%m-%dT%H:%M:%S").to_string();
-        let mut file = std::fs::OpenOptions::new()
+    fn emit(
+        &mut self,
+        event: &str,
+        alert: &Alert,
+        observations: &[Observation],
+        text: &str,
+    ) -> Result<(), std::io::Error> {
+        if let Some(syslog) = &self.syslog
+            && let Err(e) = syslog.send(event, alert, observations, text)
+        {
+            eprintln!("Failed to send syslog message: {:?}", e);
+        }
+        if self.syslog_only {
+            return Ok(());
+        }
+        let condition = alert.condition();
+        let limit = condition.limit();
+        let observation = observations.first();
+        self.write_entry(LogEntry {
+            timestamp: chrono::Utc::now().timestamp(),
+            // `Escalation step 2` is logged as `escalation`, like the syslog MSGID
+            event: event
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_lowercase(),
+            alert_id: Some(alert.id),
+            severity: Some(alert.severity.to_string()),
+            metric_id: Some(observation.map_or(alert.metric_id.clone(), |o| o.metric_id.clone())),
+            value: observation.map(|o| o.value),
+            logic: limit.map(|(logic, _)| logic.to_string()),
+            threshold: limit.map(|(_, value)| value.to_string()),
+            state: Some(match event {
+                "Resolved" => AlertState::Resolved,
+                _ => AlertState::Firing,
+            }),
+            message: text.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn write_entry(&mut self, mut entry: LogEntry) -> Result<(), std::io::Error> {
+        entry.time = Local::now().to_rfc3339();
+        let line = serde_json::to_string(&entry)?;
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let size = fs::metadata(&self.file_path).map_or(0, |m| m.len());
+        if size > 0
+            && (size + line.len() as u64 > self.max_size
+                || self
+                    .started()
+                    .is_some_and(|started| entry.timestamp - started > self.max_age))
+        {
+            self.rotate()?;
+        }
+        let mut file = OpenOptions::new()
             .create(true)
             .append(true)
             .open(&self.file_path)?;
-        use std::io::Write;
-        writeln!(file, "{}: {}", time, alert)?;
+        writeln!(file, "{}", line)?;
         Ok(())
     }
 
-    pub fn get(&self, n: usize) -> Result<Vec<String>, std::io::Error> {
-        let file = std::fs::File::open(&self.file_path)?;
-        let reader = std::io::BufReader::new(file);
-        let mut lines = Vec::new();
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                lines.push(line);
+    // Read from the file on every write, so the age survives restarts and outside edits.
+    // Falls back to the file's creation (or modification) time when the first line is unreadable.
+    fn started(&self) -> Option<i64> {
+        let file = File::open(&self.file_path).ok()?;
+        let first = BufReader::new(&file)
+            .lines()
+            .next()
+            .and_then(Result::ok)
+            .and_then(|line| parse_line(&line));
+        if let Some(entry) = first {
+            return Some(entry.timestamp);
+        }
+        let metadata = file.metadata().ok()?;
+        let time = metadata.created().or_else(|_| metadata.modified()).ok()?;
+        Some(chrono::DateTime::<chrono::Utc>::from(time).timestamp())
+    }
+
+    // Shifts the compressed files up by one, dropping the oldest, and compresses the current file
+    fn rotate(&self) -> Result<(), std::io::Error> {
+        if self.keep > 0 {
+            let _ = fs::remove_file(self.rotated_path(self.keep));
+            for index in (1..self.keep).rev() {
+                let path = self.rotated_path(index);
+                if fs::exists(&path)? {
+                    fs::rename(&path, self.rotated_path(index + 1))?;
+                }
+            }
+            let mut encoder =
+                GzEncoder::new(File::create(self.rotated_path(1))?, Compression::default());
+            std::io::copy(&mut File::open(&self.file_path)?, &mut encoder)?;
+            encoder.finish()?;
+        }
+        File::create(&self.file_path)?;
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: u32) -> String {
+        format!("{}.{}.gz", self.file_path, index)
+    }
+
+    // Newest entries first, reading the rotated files only while more entries are needed
+    pub fn query(
+        &self,
+        filter: &LogFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<LogEntry>, std::io::Error> {
+        let wanted = offset.saturating_add(limit);
+        let mut entries = Vec::new();
+        for index in 0..=self.keep {
+            if entries.len() >= wanted {
+                break;
             }
+            let reader: Box<dyn Read> = match index {
+                0 => Box::new(File::open(&self.file_path)?),
+                index => match File::open(self.rotated_path(index)) {
+                    Ok(file) => Box::new(GzDecoder::new(file)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => break,
+                    Err(e) => return Err(e),
+                },
+            };
+            // Only the newest matches of each file are kept in memory
+            let mut newest = VecDeque::new();
+            for line in BufReader::new(reader).lines() {
+                let Some(entry) = parse_line(&line?) else {
+                    continue;
+                };
+                if filter.matches(&entry) {
+                    if newest.len() == wanted - entries.len() {
+                        newest.pop_front();
+                    }
+                    newest.push_back(entry);
+                }
+            }
+            entries.extend(newest.into_iter().rev());
+        }
+        Ok(entries.into_iter().skip(offset).take(limit).collect())
+    }
+
+    // The messages of the last `n` entries (0 for all of them), oldest first
+    pub fn get(&self, n: usize) -> Result<Vec<String>, std::io::Error> {
+        let limit = match n {
+            0 => usize::MAX,
+            n => n,
+        };
+        let entries = self.query(&LogFilter::default(), 0, limit)?;
+        Ok(entries
+            .into_iter()
+            .rev()
+            .map(|entry| {
+                let time = Local
+                    .timestamp_opt(entry.timestamp, 0)
+                    .single()
+                    .unwrap_or_default()
+                    .format("%Y-%m-%dT%H:%M:%S");
+                format!("{}: {}", time, entry.message)
+            })
+            .collect())
+    }
+}
+
+// Lines written before the JSON format become `legacy` entries with the text as message
+fn parse_line(line: &str) -> Option<LogEntry> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    if let Ok(entry) = serde_json::from_str::<LogEntry>(line) {
+        return Some(entry);
+    }
+    let (time, message) = line.split_at_checked(19)?;
+    let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S").ok()?;
+    let time = Local.from_local_datetime(&time).single()?;
+    Some(LogEntry {
+        timestamp: time.timestamp(),
+        time: time.to_rfc3339(),
+        event: "legacy".to_string(),
+        message: message.trim_start_matches(": ").to_string(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::utils::notifier::tests::alert;
+
+    // A logger writing to its own directory, rotating after `max_size` bytes
+    fn logger(max_size: u64, keep: u32) -> Logger {
+        let dir = std::env::temp_dir().join(format!("sciigilo-log-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut env = EnvConfig::new();
+        env.log_file_path = dir.join("sciigilo.log").display().to_string();
+        env.log_max_size = max_size;
+        env.log_keep = keep;
+        Logger::new(&env).unwrap()
+    }
+
+    fn delivery(timestamp: i64, attempt: u32) -> Delivery {
+        Delivery {
+            alert_id: Uuid::new_v4(),
+            target: "Webhook".to_string(),
+            attempt,
+            timestamp,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn full_files_are_rotated_and_old_ones_dropped() {
+        let mut logger = logger(1024, 2);
+        let alert = alert("cpu_usage_percentage", "90");
+        for _ in 0..30 {
+            logger.log_event("Firing", &alert, &[]).unwrap();
         }
-        if n == 0 {
-            return Ok(lines);
+        assert!(fs::metadata(&logger.file_path).unwrap().len() <= 1024);
+        assert!(fs::exists(logger.rotated_path(1)).unwrap());
+        assert!(fs::exists(logger.rotated_path(2)).unwrap());
+        assert!(!fs::exists(logger.rotated_path(3)).unwrap());
+    }
+
+    #[test]
+    fn query_reads_through_rotated_files_newest_first() {
+        let mut logger = logger(2048, 5);
+        let now = chrono::Utc::now().timestamp();
+        for attempt in 1..=20 {
+            logger.log_delivery(&delivery(now, attempt)).unwrap();
         }
+        assert!(fs::exists(logger.rotated_path(3)).unwrap());
+        let attempts: Vec<u32> = logger
+            .query(&LogFilter::default(), 0, 100)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.delivery.as_ref().unwrap().attempt)
+            .collect();
+        assert_eq!(attempts, (1..=20).rev().collect::<Vec<_>>());
+
+        let page: Vec<u32> = logger
+            .query(&LogFilter::default(), 5, 10)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.delivery.as_ref().unwrap().attempt)
+            .collect();
+        assert_eq!(page, (6..=15).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn age_is_taken_from_the_file_after_a_restart() {
+        let mut first = logger(1048576, 5);
+        let now = chrono::Utc::now().timestamp();
+        first.log_delivery(&delivery(now - 8 * 86400, 1)).unwrap();
+        let mut env = EnvConfig::new();
+        env.log_file_path = first.file_path.clone();
+        let mut restarted = Logger::new(&env).unwrap();
 
-        let start = if n > lines.len() { 0 } else { lines.len() - n };
-        let end = lines.len();
-        Ok(lines[start..end].to_vec())
+        restarted.log_delivery(&delivery(now, 2)).unwrap();
+        assert!(fs::exists(restarted.rotated_path(1)).unwrap());
+        let entries = restarted.query(&LogFilter::default(), 0, 10).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(restarted.get(0).unwrap().len(), 2);
+        let current = fs::read_to_string(&restarted.file_path).unwrap();
+        assert_eq!(current.lines().count(), 1);
     }
 }
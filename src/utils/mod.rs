@@ -1,5 +1,13 @@
+pub mod backtest;
+pub mod chat;
 pub mod collector;
 pub mod config;
 pub mod db;
+pub mod email;
+pub mod exec;
 pub mod log;
+pub mod mqtt;
+pub mod notifier;
+pub mod syslog;
+pub mod template;
 pub mod watchtower;
@@ -0,0 +1,716 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::models::{
+    alert::{Alert, Observation},
+    channel::{ChannelKind, Email},
+    delivery::Delivery,
+    exec::Exec,
+    incident::AlertState,
+    request::{Auth, BodyFormat, Request, RequestType},
+};
+use hmac::{Hmac, KeyInit, Mac};
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, transport::smtp::response::Code,
+};
+use minijinja::Value;
+use reqwest::{
+    Method,
+    header::{CONTENT_TYPE, HeaderValue},
+};
+use sha2::Sha256;
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::{chat, config::EnvConfig, db, email, exec, log::Logger, template};
+
+// Result of one delivery attempt, `retry` marks failures worth another try
+enum Outcome {
+    Sent(Option<u16>),
+    Failed {
+        status: Option<u16>,
+        error: String,
+        retry: bool,
+    },
+}
+
+// The alerts waiting for one email channel, with their template context
+type EmailBatch = (Email, Vec<(Uuid, Value)>);
+
+// Shares one HTTP client between all notifications and records every delivery attempt
+#[derive(Clone)]
+pub struct Notifier {
+    client: reqwest::Client,
+    pool: Arc<SqlitePool>,
+    timeout: Duration,
+    retries: u32,
+    dashboard_url: Option<String>,
+    exec_enabled: bool,
+    logger: Logger,
+    // Emails wait here until the end of the check so simultaneous alerts share one message
+    emails: Arc<Mutex<Vec<EmailBatch>>>,
+}
+
+impl Notifier {
+    pub fn new(pool: Arc<SqlitePool>, env: &EnvConfig, logger: Logger) -> Self {
+        let timeout = Duration::from_secs(env.http_timeout);
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .expect("Failed to build HTTP client");
+        Notifier {
+            client,
+            pool,
+            timeout,
+            retries: env.http_retries,
+            dashboard_url: env.public_url.clone(),
+            exec_enabled: env.exec_actions_enabled,
+            logger,
+            emails: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    // Deliveries run in the background so retries never hold up the next check
+    pub fn notify(
+        &self,
+        kind: &ChannelKind,
+        alert: &Alert,
+        observations: &[Observation],
+        state: AlertState,
+    ) {
+        let dashboard_url = self.dashboard_url.as_deref();
+        let http_request = match kind {
+            ChannelKind::Webhook(request) => {
+                build_request(&self.client, request, alert, observations, state)
+            }
+            ChannelKind::Slack(slack) => {
+                let payload = chat::slack_payload(slack, alert, observations, state, dashboard_url);
+                build_json(self.client.post(&slack.webhook_url), &payload)
+            }
+            ChannelKind::Discord(discord) => {
+                let payload =
+                    chat::discord_payload(discord, alert, observations, state, dashboard_url);
+                build_json(self.client.post(&discord.webhook_url), &payload)
+            }
+            ChannelKind::Telegram(telegram) => {
+                let payload =
+                    chat::telegram_payload(telegram, alert, observations, state, dashboard_url);
+                let url = format!(
+                    "{}/bot{}/sendMessage",
+                    telegram.api_url.trim_end_matches('/'),
+                    telegram.bot_token
+                );
+                build_json(self.client.post(url), &payload)
+            }
+            ChannelKind::Ntfy(ntfy) => {
+                let payload = chat::ntfy_payload(ntfy, alert, observations, state, dashboard_url);
+                let mut builder = self.client.post(&ntfy.server);
+                if let Some(token) = &ntfy.token {
+                    builder = builder.bearer_auth(token);
+                }
+                build_json(builder, &payload)
+            }
+            ChannelKind::Gotify(gotify) => {
+                let payload =
+                    chat::gotify_payload(gotify, alert, observations, state, dashboard_url);
+                let url = format!("{}/message", gotify.server.trim_end_matches('/'));
+                build_json(
+                    self.client.post(url).header("X-Gotify-Key", &gotify.token),
+                    &payload,
+                )
+            }
+            ChannelKind::Matrix(matrix) => {
+                let payload = chat::matrix_payload(alert, observations, state, dashboard_url);
+                // The transaction id makes retries of the same message idempotent
+                let mut url = match reqwest::Url::parse(&matrix.homeserver) {
+                    Ok(url) => url,
+                    Err(e) => {
+                        eprintln!("Invalid Matrix homeserver {}: {}", matrix.homeserver, e);
+                        return;
+                    }
+                };
+                let transaction = Uuid::new_v4().to_string();
+                if let Ok(mut segments) = url.path_segments_mut() {
+                    segments.pop_if_empty().extend([
+                        "_matrix",
+                        "client",
+                        "v3",
+                        "rooms",
+                        &matrix.room_id,
+                        "send",
+                        "m.room.message",
+                        &transaction,
+                    ]);
+                }
+                build_json(
+                    self.client.put(url).bearer_auth(&matrix.access_token),
+                    &payload,
+                )
+            }
+            ChannelKind::Teams(teams) => {
+                let payload = chat::teams_payload(alert, observations, state, dashboard_url);
+                build_json(self.client.post(&teams.webhook_url), &payload)
+            }
+            ChannelKind::PagerDuty(pagerduty) => {
+                let event_action = match state {
+                    AlertState::Firing => "trigger",
+                    AlertState::Resolved => "resolve",
+                };
+                let payload = chat::pagerduty_payload(
+                    pagerduty,
+                    alert,
+                    observations,
+                    event_action,
+                    dashboard_url,
+                );
+                build_json(self.client.post(&pagerduty.url), &payload)
+            }
+            ChannelKind::Email(email) => {
+                let context = template::alert_context(alert, observations, state);
+                let mut emails = self.emails.lock().unwrap_or_else(|e| e.into_inner());
+                match emails.iter_mut().find(|(e, _)| e == email) {
+                    Some((_, alerts)) => alerts.push((alert.id, context)),
+                    None => emails.push((email.clone(), vec![(alert.id, context)])),
+                }
+                return;
+            }
+            ChannelKind::Exec(command) => {
+                self.run(command, alert, observations, state);
+                return;
+            }
+        };
+        self.send(alert.id, kind.to_string(), http_request);
+    }
+
+    // Passes an acknowledgement on to the targets that track incidents, others are skipped
+    pub fn acknowledge(&self, kind: &ChannelKind, alert: &Alert) {
+        let http_request = match kind {
+            ChannelKind::PagerDuty(pagerduty) => {
+                let payload = chat::pagerduty_payload(pagerduty, alert, &[], "acknowledge", None);
+                build_json(self.client.post(&pagerduty.url), &payload)
+            }
+            _ => return,
+        };
+        self.send(alert.id, kind.to_string(), http_request);
+    }
+
+    fn send(&self, alert_id: Uuid, target: String, http_request: Option<reqwest::Request>) {
+        let Some(http_request) = http_request else {
+            return;
+        };
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            let client = notifier.client.clone();
+            let send = || {
+                let request = http_request.try_clone();
+                let client = client.clone();
+                async move { send_http(&client, request).await }
+            };
+            notifier.deliver(&[alert_id], &target, send).await
+        });
+    }
+
+    // Commands are not retried, a failed cleanup is better looked at than repeated
+    fn run(&self, command: &Exec, alert: &Alert, observations: &[Observation], state: AlertState) {
+        let mut logger = self.logger.clone();
+        if !self.exec_enabled {
+            if let Err(e) = logger.log_command(
+                alert,
+                observations,
+                "skipped, EXEC_ACTIONS_ENABLED is off",
+                "",
+            ) {
+                eprintln!("Failed to log command: {:?}", e);
+            }
+            return;
+        }
+        let notifier = self.clone();
+        let command = command.clone();
+        let alert = alert.clone();
+        let observations = observations.to_vec();
+        tokio::spawn(async move {
+            let target = command.to_string();
+            let run = || {
+                let mut logger = logger.clone();
+                let (command, alert, observations) = (&command, &alert, &observations);
+                async move {
+                    let (outcome, result, output) =
+                        match exec::run(command, alert, observations, state).await {
+                            Ok(execution) => {
+                                let status = execution.status.and_then(|s| u16::try_from(s).ok());
+                                match execution.status {
+                                    Some(0) => (
+                                        Outcome::Sent(status),
+                                        "succeeded".to_string(),
+                                        execution.output,
+                                    ),
+                                    code => {
+                                        let result = match code {
+                                            Some(code) => format!("exited with status {}", code),
+                                            None => "was killed by a signal".to_string(),
+                                        };
+                                        let outcome = Outcome::Failed {
+                                            status,
+                                            error: result.clone(),
+                                            retry: false,
+                                        };
+                                        (outcome, result, execution.output)
+                                    }
+                                }
+                            }
+                            Err(e) => (
+                                Outcome::Failed {
+                                    status: None,
+                                    error: e.clone(),
+                                    retry: false,
+                                },
+                                format!("failed: {}", e),
+                                String::new(),
+                            ),
+                        };
+                    if let Err(e) = logger.log_command(alert, observations, &result, &output) {
+                        eprintln!("Failed to log command: {:?}", e);
+                    }
+                    outcome
+                }
+            };
+            notifier.deliver(&[alert.id], &target, run).await
+        });
+    }
+
+    // Sends the emails collected during a check, one per channel
+    pub fn flush(&self) {
+        let batches = std::mem::take(&mut *self.emails.lock().unwrap_or_else(|e| e.into_inner()));
+        for (email, alerts) in batches {
+            let target = ChannelKind::Email(email.clone()).to_string();
+            let (alert_ids, contexts): (Vec<Uuid>, Vec<Value>) = alerts.into_iter().unzip();
+            let message =
+                match email::build_message(&email, contexts, self.dashboard_url.as_deref()) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        eprintln!("Failed to build {}: {}", target, e);
+                        continue;
+                    }
+                };
+            let transport = match email::build_transport(&email, self.timeout) {
+                Ok(transport) => transport,
+                Err(e) => {
+                    eprintln!("Failed to connect {}: {}", target, e);
+                    continue;
+                }
+            };
+            let notifier = self.clone();
+            tokio::spawn(async move {
+                let send = || {
+                    let transport = transport.clone();
+                    let message = message.clone();
+                    async move { send_email(&transport, message).await }
+                };
+                notifier.deliver(&alert_ids, &target, send).await
+            });
+        }
+    }
+
+    // Failures marked for retry are repeated with exponential backoff, everything else is final
+    async fn deliver<F, Fut>(&self, alert_ids: &[Uuid], target: &str, send: F)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Outcome>,
+    {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let started = std::time::Instant::now();
+            let outcome = send().await;
+            let latency_ms = started.elapsed().as_millis() as u64;
+            let (status, error, retry) = match outcome {
+                Outcome::Sent(status) => (status, None, false),
+                Outcome::Failed {
+                    status,
+                    error,
+                    retry,
+                } => {
+                    eprintln!("Delivery {} of {} failed: {}", attempt, target, error);
+                    (status, Some(error), retry)
+                }
+            };
+            let last = !retry || attempt > self.retries;
+            let mut logger = self.logger.clone();
+            for alert_id in alert_ids {
+                let delivery = Delivery {
+                    alert_id: *alert_id,
+                    target: target.to_string(),
+                    attempt,
+                    timestamp: chrono::Utc::now().timestamp(),
+                    status,
+                    latency_ms,
+                    error: error.clone(),
+                };
+                if let Err(e) = db::insert_delivery(&self.pool, &delivery).await {
+                    eprintln!("Failed to record delivery: {:?}", e);
+                }
+                // The outcome of the last attempt goes to the alert log as well
+                if last && let Err(e) = logger.log_delivery(&delivery) {
+                    eprintln!("Failed to log delivery: {:?}", e);
+                }
+            }
+            if last {
+                return;
+            }
+            tokio::time::sleep(Duration::from_secs(1 << (attempt - 1).min(6))).await;
+        }
+    }
+}
+
+// Network errors and 5xx answers are worth another try
+async fn send_http(client: &reqwest::Client, request: Option<reqwest::Request>) -> Outcome {
+    let Some(request) = request else {
+        return Outcome::Failed {
+            status: None,
+            error: "Request body cannot be resent".to_string(),
+            retry: false,
+        };
+    };
+    match client.execute(request).await {
+        Ok(response) if response.status().is_success() => {
+            Outcome::Sent(Some(response.status().as_u16()))
+        }
+        Ok(response) => Outcome::Failed {
+            status: Some(response.status().as_u16()),
+            error: format!("HTTP {}", response.status()),
+            retry: response.status().is_server_error(),
+        },
+        // The url is already in the target, and may carry a token for some services
+        Err(e) => Outcome::Failed {
+            status: None,
+            error: error_chain(&e.without_url()),
+            retry: true,
+        },
+    }
+}
+
+// Only permanent (5xx) SMTP answers are final, connection problems and 4xx are retried
+async fn send_email(transport: &AsyncSmtpTransport<Tokio1Executor>, message: Message) -> Outcome {
+    let smtp_code = |code: Code| code.to_string().parse().ok();
+    match transport.send(message).await {
+        Ok(response) => Outcome::Sent(smtp_code(response.code())),
+        Err(e) => Outcome::Failed {
+            status: e.status().and_then(smtp_code),
+            error: error_chain(&e),
+            retry: !e.is_permanent(),
+        },
+    }
+}
+
+// Signs timestamp and body so receivers can reject forged or replayed calls
+fn sign(http_request: &mut reqwest::Request, secret: &str) {
+    let timestamp = chrono::Utc::now().timestamp().to_string();
+    let body = http_request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .unwrap_or_default();
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+    let headers = http_request.headers_mut();
+    if let (Ok(timestamp), Ok(signature)) = (
+        HeaderValue::from_str(&timestamp),
+        HeaderValue::from_str(&signature),
+    ) {
+        headers.insert("X-Sciigilo-Timestamp", timestamp);
+        headers.insert("X-Sciigilo-Signature", signature);
+    }
+}
+
+// reqwest keeps the interesting part (refused, timed out, ...) in the source
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        let text = e.to_string();
+        if !message.ends_with(&text) {
+            message.push_str(&format!(": {}", text));
+        }
+        source = e.source();
+    }
+    message
+}
+
+fn build_json(
+    builder: reqwest::RequestBuilder,
+    payload: &serde_json::Value,
+) -> Option<reqwest::Request> {
+    match builder.json(payload).build() {
+        Ok(http_request) => Some(http_request),
+        Err(e) => {
+            eprintln!("Failed to build request: {:?}", e);
+            None
+        }
+    }
+}
+
+fn build_request(
+    client: &reqwest::Client,
+    request: &Request,
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+) -> Option<reqwest::Request> {
+    let fill = |text: &str| template::render(text, alert, observations, state);
+    let method = match request.request_type {
+        RequestType::Get => Method::GET,
+        RequestType::Post => Method::POST,
+        RequestType::Put => Method::PUT,
+        RequestType::Patch => Method::PATCH,
+        RequestType::Delete => Method::DELETE,
+    };
+    let mut builder = client.request(method, fill(&request.url));
+    if !request.query.is_empty() {
+        let query: Vec<(&String, String)> =
+            request.query.iter().map(|(k, v)| (k, fill(v))).collect();
+        builder = builder.query(&query);
+    }
+    for (name, value) in &request.headers {
+        builder = builder.header(name, fill(value));
+    }
+    builder = match &request.auth {
+        Some(Auth::Basic { username, password }) => {
+            builder.basic_auth(fill(username), Some(fill(password)))
+        }
+        Some(Auth::Bearer { token }) => builder.bearer_auth(fill(token)),
+        None => builder,
+    };
+
+    // GET requests never carried a body
+    let mut content_type = None;
+    if request.request_type != RequestType::Get {
+        let payload = fill(&request.body.payload);
+        builder = match request.body.format {
+            BodyFormat::Json => {
+                content_type = Some("application/json");
+                match request.body.raw_json {
+                    true => builder.body(payload),
+                    false => builder.body(serde_json::Value::String(payload).to_string()),
+                }
+            }
+            BodyFormat::XWwwFormUrlEncoded => {
+                let form = payload
+                    .split('&')
+                    .map(|kv| {
+                        let mut split = kv.split('=');
+                        (
+                            split.next().unwrap_or("").to_string(),
+                            split.next().unwrap_or("").to_string(),
+                        )
+                    })
+                    .collect::<HashMap<String, String>>();
+                builder.form(&form)
+            }
+            BodyFormat::Text => {
+                content_type = Some("text/plain");
+                builder.body(payload)
+            }
+        };
+        content_type = request.body.content_type.as_deref().or(content_type);
+    }
+
+    let mut http_request = match builder.build() {
+        Ok(http_request) => http_request,
+        Err(e) => {
+            eprintln!("Failed to build {}: {:?}", request, e);
+            return None;
+        }
+    };
+    if let Some(content_type) = content_type {
+        match HeaderValue::from_str(content_type) {
+            Ok(value) => {
+                http_request.headers_mut().insert(CONTENT_TYPE, value);
+            }
+            Err(_) => eprintln!("Invalid content type: {}", content_type),
+        }
+    }
+    if let Some(signing) = &request.signing {
+        sign(&mut http_request, &signing.secret);
+    }
+    Some(http_request)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use super::*;
+    use crate::models::{alert::Logic, channel::SmtpTls, request::Body};
+
+    // A notifier with an in-memory database and its own log file
+    pub(crate) async fn notifier(dashboard_url: Option<&str>) -> Notifier {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let mut env = EnvConfig::new();
+        env.log_file_path = std::env::temp_dir()
+            .join(format!("sciigilo-test-{}.log", Uuid::new_v4()))
+            .display()
+            .to_string();
+        env.public_url = dashboard_url.map(str::to_string);
+        env.http_retries = 0;
+        let pool = Arc::new(pool);
+        db::init_db(&pool, 1).await;
+        Notifier::new(pool, &env, Logger::new(&env).unwrap())
+    }
+
+    pub(crate) fn alert(metric_id: &str, value: &str) -> Alert {
+        Alert {
+            metric_id: metric_id.to_string(),
+            logic: Logic::Gt,
+            value: value.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // Just enough SMTP to accept messages, every DATA block is passed on
+    async fn smtp_server() -> (u16, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = stream.into_split();
+                    let mut lines = BufReader::new(read).lines();
+                    write.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+                    let mut data: Option<String> = None;
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if let Some(message) = data.as_mut() {
+                            if line == "." {
+                                sender.send(data.take().unwrap()).unwrap();
+                                write.write_all(b"250 queued\r\n").await.unwrap();
+                            } else {
+                                message.push_str(&line);
+                                message.push('\n');
+                            }
+                            continue;
+                        }
+                        let reply: &[u8] = match line.get(..4).unwrap_or_default() {
+                            "DATA" => {
+                                data = Some(String::new());
+                                b"354 go ahead\r\n"
+                            }
+                            "QUIT" => b"221 bye\r\n",
+                            _ => b"250 ok\r\n",
+                        };
+                        write.write_all(reply).await.unwrap();
+                    }
+                });
+            }
+        });
+        (port, receiver)
+    }
+
+    #[tokio::test]
+    async fn simultaneous_alerts_share_one_multipart_email() {
+        let (port, mut messages) = smtp_server().await;
+        let notifier = notifier(Some("http://dashboard.local")).await;
+        let kind = ChannelKind::Email(Email {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: SmtpTls::None,
+            from: "sciigilo <alerts@example.com>".to_string(),
+            to: vec!["ops@example.com".to_string()],
+            ..Default::default()
+        });
+
+        notifier.notify(
+            &kind,
+            &alert("cpu_usage_percentage", "90"),
+            &[],
+            AlertState::Firing,
+        );
+        notifier.notify(
+            &kind,
+            &alert("mem_usage_percentage", "80"),
+            &[],
+            AlertState::Firing,
+        );
+        notifier.flush();
+
+        let message = tokio::time::timeout(Duration::from_secs(10), messages.recv())
+            .await
+            .expect("no email within 10 seconds")
+            .unwrap();
+        assert!(message.contains("Subject: 2 alerts on"));
+        assert!(message.contains("multipart/alternative"));
+        assert!(message.contains("Content-Type: text/plain"));
+        assert!(message.contains("Content-Type: text/html"));
+        assert!(message.contains("cpu_usage_percentage > 90"));
+        assert!(message.contains("mem_usage_percentage > 80"));
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(messages.try_recv().is_err(), "alerts were sent separately");
+    }
+
+    #[tokio::test]
+    async fn commands_are_refused_unless_enabled() {
+        let notifier = notifier(None).await;
+        let marker = std::env::temp_dir().join(format!("sciigilo-exec-{}", Uuid::new_v4()));
+        let kind = ChannelKind::Exec(Exec {
+            command: "touch".to_string(),
+            args: vec![marker.display().to_string()],
+            timeout: 5,
+            run_as: None,
+        });
+        notifier.notify(
+            &kind,
+            &alert("cpu_usage_percentage", "90"),
+            &[],
+            AlertState::Firing,
+        );
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!marker.exists(), "the command ran");
+        let log = notifier.logger.get(10).unwrap().join("\n");
+        assert!(log.contains("Command skipped, EXEC_ACTIONS_ENABLED is off"));
+    }
+
+    #[tokio::test]
+    async fn json_bodies_of_older_rules_stay_quoted() {
+        let body = |raw_json: bool| {
+            let request = Request {
+                request_type: RequestType::Post,
+                url: "http://hook.local".to_string(),
+                body: Body {
+                    raw_json,
+                    ..Body::new(BodyFormat::Json, "{\"state\": \"{{ state }}\"}".to_string())
+                },
+                ..Default::default()
+            };
+            let client = reqwest::Client::new();
+            let alert = alert("cpu_usage_percentage", "90");
+            let http_request =
+                build_request(&client, &request, &alert, &[], AlertState::Firing).unwrap();
+            assert_eq!(http_request.headers()[CONTENT_TYPE], "application/json");
+            let bytes = http_request.body().unwrap().as_bytes().unwrap().to_vec();
+            String::from_utf8(bytes).unwrap()
+        };
+        assert_eq!(body(true), r#"{"state": "firing"}"#);
+        assert_eq!(body(false), r#""{\"state\": \"firing\"}""#);
+
+        // Saved rules without the flag are older ones
+        let saved: Body = serde_json::from_str(r#"{"format": "json", "payload": "{}"}"#).unwrap();
+        assert!(!saved.raw_json);
+    }
+}
@@ -0,0 +1,369 @@
+use std::{net::SocketAddr, time::Duration};
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, UdpSocket, UnixDatagram},
+    sync::mpsc,
+    time::Instant,
+};
+
+use crate::models::alert::{Alert, Observation, Severity};
+
+// Private enterprise number reserved for documentation (RFC 5612), used for the SD-ID
+const SD_ID: &str = "alert@32473";
+// Messages waiting for the sender task, further ones are dropped while it is full
+const QUEUE_SIZE: usize = 1000;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// A failed TCP connect waits 1s, 2s, 4s, ... up to a minute before the next attempt
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+enum Transport {
+    Udp(String),
+    Tcp(String),
+    Unix(String),
+}
+
+// Sends every alert log entry as an RFC 5424 message, next to or instead of the log file.
+// Messages are handed to a background task so a slow or unreachable host never blocks the caller.
+#[derive(Clone)]
+pub struct Syslog {
+    facility: u8,
+    hostname: String,
+    queue: mpsc::Sender<String>,
+}
+
+impl Syslog {
+    // `udp://host:514`, `tcp://host:601` or `unix:///dev/log`, must be called within the runtime
+    pub fn new(url: &str, facility: &str) -> Result<Syslog, String> {
+        let transport = match url.split_once("://") {
+            Some(("udp", address)) => Transport::Udp(with_port(address, 514)),
+            Some(("tcp", address)) => Transport::Tcp(with_port(address, 601)),
+            Some(("unix", path)) if !path.is_empty() => Transport::Unix(path.to_string()),
+            _ => return Err(format!("Unsupported syslog url {}", url)),
+        };
+        let facility = parse_facility(facility)?;
+        let (queue, messages) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(deliver(transport, messages));
+        Ok(Syslog {
+            facility,
+            hostname: sysinfo::System::host_name().unwrap_or_else(|| "-".to_string()),
+            queue,
+        })
+    }
+
+    pub fn send(
+        &self,
+        event: &str,
+        alert: &Alert,
+        observations: &[Observation],
+        message: &str,
+    ) -> Result<(), std::io::Error> {
+        let line = self.format(event, alert, observations, message);
+        self.queue
+            .try_send(line)
+            .map_err(|e| std::io::Error::other(format!("syslog queue: {}", e)))
+    }
+
+    // <PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID [SD] MSG
+    fn format(
+        &self,
+        event: &str,
+        alert: &Alert,
+        observations: &[Observation],
+        message: &str,
+    ) -> String {
+        // Recoveries and acknowledgements are informational whatever the alert's severity
+        let severity = match (event, &alert.severity) {
+            ("Resolved" | "Acknowledged", _) => 6,
+            (_, Severity::Critical) => 2,
+            (_, Severity::Warning) => 4,
+            (_, Severity::Info) => 6,
+            (_, Severity::Custom(_)) => 5,
+        };
+        let condition = alert.condition();
+        let mut params = vec![
+            ("id", alert.id.to_string()),
+            ("event", event.to_string()),
+            ("severity", alert.severity.to_string()),
+        ];
+        if let Some(observation) = observations.first() {
+            params.push(("metric", observation.metric_id.clone()));
+            params.push(("value", observation.value.to_string()));
+        } else {
+            params.push(("metric", alert.metric_id.clone()));
+        }
+        if let Some((logic, threshold)) = condition.limit() {
+            params.push(("logic", logic.to_string()));
+            params.push(("threshold", threshold.to_string()));
+        }
+        let data: String = params
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| format!(" {}=\"{}\"", name, escape(value)))
+            .collect();
+        format!(
+            "<{}>1 {} {} sciigilo {} {} [{}{}] {}",
+            self.facility * 8 + severity,
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            self.hostname,
+            std::process::id(),
+            msg_id(event),
+            SD_ID,
+            data,
+            message
+        )
+    }
+}
+
+// Writes queued messages one at a time, keeping a TCP connection open between them
+async fn deliver(transport: Transport, mut messages: mpsc::Receiver<String>) {
+    let mut stream: Option<TcpStream> = None;
+    let mut backoff = Duration::ZERO;
+    let mut retry_at = Instant::now();
+    while let Some(line) = messages.recv().await {
+        let result = match &transport {
+            Transport::Udp(address) => send_udp(address, &line).await,
+            Transport::Unix(path) => match UnixDatagram::unbound() {
+                Ok(socket) => socket.send_to(line.as_bytes(), path).await.map(|_| ()),
+                Err(e) => Err(e),
+            },
+            // Octet counting framing (RFC 6587), so messages may contain newlines
+            Transport::Tcp(address) => {
+                let framed = format!("{} {}", line.len(), line);
+                if let Some(connection) = stream.as_mut()
+                    && connection.write_all(framed.as_bytes()).await.is_ok()
+                {
+                    continue;
+                }
+                stream = None;
+                // Entries logged while backing off are dropped instead of redialling for each one
+                if Instant::now() < retry_at {
+                    continue;
+                }
+                match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address)).await {
+                    Ok(Ok(mut connection)) => {
+                        backoff = Duration::ZERO;
+                        let result = connection.write_all(framed.as_bytes()).await;
+                        stream = Some(connection);
+                        result
+                    }
+                    Ok(Err(e)) => Err(e),
+                    Err(_) => Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "connect timed out",
+                    )),
+                }
+                .inspect_err(|_| {
+                    backoff = (backoff * 2).clamp(Duration::from_secs(1), MAX_BACKOFF);
+                    retry_at = Instant::now() + backoff;
+                })
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to send syslog message: {:?}", e);
+        }
+    }
+}
+
+// Binds to the same address family as the host, an IPv4 socket cannot reach an IPv6 host
+async fn send_udp(address: &str, line: &str) -> Result<(), std::io::Error> {
+    let target = tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| std::io::Error::other(format!("{} did not resolve", address)))?;
+    let local = match target {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.send_to(line.as_bytes(), target).await.map(|_| ())
+}
+
+// The first word of the event, e.g. `firing` or `escalation`
+fn msg_id(event: &str) -> String {
+    event
+        .split_whitespace()
+        .next()
+        .unwrap_or("alert")
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(32)
+        .collect()
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+fn with_port(address: &str, port: u16) -> String {
+    match address.rsplit_once(':') {
+        Some((_, p)) if p.parse::<u16>().is_ok() => address.to_string(),
+        _ => format!("{}:{}", address, port),
+    }
+}
+
+fn parse_facility(facility: &str) -> Result<u8, String> {
+    match facility {
+        "user" => Ok(1),
+        "daemon" => Ok(3),
+        "auth" => Ok(4),
+        "syslog" => Ok(5),
+        facility => facility
+            .strip_prefix("local")
+            .and_then(|n| n.parse::<u8>().ok())
+            .filter(|n| *n <= 7)
+            .map(|n| 16 + n)
+            .ok_or_else(|| format!("Unknown syslog facility {}", facility)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::utils::notifier::tests::alert;
+
+    fn syslog(facility: u8) -> Syslog {
+        let (queue, _) = mpsc::channel(1);
+        Syslog {
+            facility,
+            hostname: "web-1".to_string(),
+            queue,
+        }
+    }
+
+    #[test]
+    fn pri_combines_facility_and_severity() {
+        let mut alert = alert("cpu_usage_percentage", "90");
+        alert.severity = Severity::Critical;
+        let local0 = syslog(parse_facility("local0").unwrap());
+        assert!(
+            local0
+                .format("Firing", &alert, &[], "")
+                .starts_with("<130>1 ")
+        );
+        assert!(
+            local0
+                .format("Resolved", &alert, &[], "")
+                .starts_with("<134>1 ")
+        );
+        alert.severity = Severity::Warning;
+        let daemon = syslog(parse_facility("daemon").unwrap());
+        assert!(
+            daemon
+                .format("Firing", &alert, &[], "")
+                .starts_with("<28>1 ")
+        );
+        assert!(
+            daemon
+                .format("Acknowledged", &alert, &[], "")
+                .starts_with("<30>1 ")
+        );
+    }
+
+    #[test]
+    fn header_and_structured_data_describe_the_alert() {
+        let alert = alert("cpu_usage_percentage", "90");
+        let observations = [Observation {
+            metric_id: "custom_say_\"hi\"]".to_string(),
+            value: 97.5,
+            baseline: None,
+        }];
+        let line = syslog(1).format("Escalation step 1", &alert, &observations, "text");
+        let header: Vec<&str> = line.splitn(7, ' ').collect();
+        assert!(chrono::DateTime::parse_from_rfc3339(header[1]).is_ok());
+        assert_eq!(
+            header[2..5],
+            ["web-1", "sciigilo", &std::process::id().to_string()]
+        );
+        assert_eq!(header[5], "escalation");
+        assert_eq!(
+            header[6],
+            format!(
+                "[alert@32473 id=\"{}\" event=\"Escalation step 1\" severity=\"{}\" metric=\"custom_say_\\\"hi\\\"\\]\" value=\"97.5\" logic=\">\" threshold=\"90\"] text",
+                alert.id, alert.severity
+            )
+        );
+    }
+
+    #[test]
+    fn param_values_escape_quote_backslash_and_bracket() {
+        assert_eq!(escape(r#"a"b\c]d"#), r#"a\"b\\c\]d"#);
+        assert_eq!(escape("plain [text]"), "plain [text\\]");
+    }
+
+    #[test]
+    fn facilities_are_named_like_syslog_does() {
+        assert_eq!(parse_facility("user"), Ok(1));
+        assert_eq!(parse_facility("daemon"), Ok(3));
+        assert_eq!(parse_facility("auth"), Ok(4));
+        assert_eq!(parse_facility("syslog"), Ok(5));
+        assert_eq!(parse_facility("local0"), Ok(16));
+        assert_eq!(parse_facility("local7"), Ok(23));
+        assert!(parse_facility("local8").is_err());
+        assert!(parse_facility("kern").is_err());
+    }
+
+    #[tokio::test]
+    async fn tcp_messages_are_octet_counted() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("tcp://{}", listener.local_addr().unwrap());
+        let syslog = Syslog::new(&url, "daemon").unwrap();
+        let alert = alert("cpu_usage_percentage", "90");
+        syslog
+            .send("Command", &alert, &[], "first\n    output")
+            .unwrap();
+        syslog.send("Resolved", &alert, &[], "second").unwrap();
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut messages = Vec::new();
+        for _ in 0..2 {
+            let mut length = Vec::new();
+            stream.read_until(b' ', &mut length).await.unwrap();
+            let length: usize = std::str::from_utf8(&length)
+                .unwrap()
+                .trim()
+                .parse()
+                .unwrap();
+            let mut message = vec![0; length];
+            stream.read_exact(&mut message).await.unwrap();
+            messages.push(String::from_utf8(message).unwrap());
+        }
+        assert!(messages[0].ends_with("] first\n    output"));
+        assert!(messages[1].ends_with("] second"));
+    }
+
+    #[tokio::test]
+    async fn udp_reaches_ipv6_hosts() {
+        // Hosts without IPv6 have nothing to test
+        let Ok(socket) = UdpSocket::bind("[::1]:0").await else {
+            return;
+        };
+        let url = format!("udp://{}", socket.local_addr().unwrap());
+        let syslog = Syslog::new(&url, "user").unwrap();
+        syslog
+            .send("Firing", &alert("cpu_usage_percentage", "90"), &[], "over")
+            .unwrap();
+        let mut buffer = [0; 2048];
+        let (length, _) =
+            tokio::time::timeout(Duration::from_secs(10), socket.recv_from(&mut buffer))
+                .await
+                .expect("no datagram within 10 seconds")
+                .unwrap();
+        assert!(
+            std::str::from_utf8(&buffer[..length])
+                .unwrap()
+                .ends_with("] over")
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use minijinja::{AutoEscape, Environment, Value, value::Serde};
+use serde::Serialize;
+
+use crate::models::{
+    alert::{Alert, Baseline, Observation},
+    incident::AlertState,
+};
+
+// Everything a request template can reference, e.g. `{{ value | number(1) }}` or `{{ labels.env }}`
+#[derive(Serialize)]
+struct Context<'a> {
+    alert_id: String,
+    metric_id: &'a str,
+    value: Option<f32>,
+    threshold: Option<&'a str>,
+    logic: Option<String>,
+    condition: String,
+    severity: String,
+    state: String,
+    hostname: String,
+    timestamp: i64,
+    time: String,
+    labels: &'a BTreeMap<String, String>,
+    metrics: BTreeMap<&'a str, f32>,
+    baseline: Option<&'a Baseline>,
+    observations: &'a [Observation],
+}
+
+fn environment<'a>() -> Environment<'a> {
+    let mut env = Environment::new();
+    env.add_filter("number", number);
+    env.add_filter("json_escape", json_escape);
+    env
+}
+
+// `{{ 3.14159 | number(2) }}` renders `3.14`
+fn number(value: f64, precision: Option<usize>) -> String {
+    format!("{:.*}", precision.unwrap_or(2), value)
+}
+
+// Escapes a string for use inside a JSON string literal, without the surrounding quotes
+fn json_escape(value: String) -> String {
+    let quoted = serde_json::to_string(&value).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+pub fn validate(text: &str) -> Result<(), String> {
+    environment()
+        .template_from_str(text)
+        .map(|_| ())
+        .map_err(|e| format!("Invalid template {:?}: {}", text, e))
+}
+
+// The variables of a single alert, also used per entry of batched templates
+pub fn alert_context(alert: &Alert, observations: &[Observation], state: AlertState) -> Value {
+    let condition = alert.condition();
+    let limit = condition.limit();
+    let now = chrono::Local::now();
+    let context = Context {
+        alert_id: alert.id.to_string(),
+        metric_id: observations
+            .first()
+            .map_or(alert.metric_id.as_str(), |o| o.metric_id.as_str()),
+        value: observations.first().map(|o| o.value),
+        threshold: limit.map(|(_, value)| value),
+        logic: limit.map(|(logic, _)| logic.to_string()),
+        condition: condition.to_string(),
+        severity: alert.severity.to_string(),
+        state: state.to_string(),
+        hostname: sysinfo::System::host_name().unwrap_or_default(),
+        timestamp: now.timestamp(),
+        time: now.to_rfc3339(),
+        labels: &alert.labels,
+        metrics: observations
+            .iter()
+            .map(|o| (o.metric_id.as_str(), o.value))
+            .collect(),
+        baseline: observations.iter().find_map(|o| o.baseline.as_ref()),
+        observations,
+    };
+    Value::from(Serde(&context))
+}
+
+// `html` escapes every rendered value, for templates that produce markup
+pub fn render_context(text: &str, context: Value, html: bool) -> Result<String, String> {
+    let mut env = environment();
+    if html {
+        env.set_auto_escape_callback(|_| AutoEscape::Html);
+    }
+    env.render_str(text, context).map_err(|e| e.to_string())
+}
+
+// Renders the template first and then the single brace placeholders of older rules
+pub fn render(
+    text: &str,
+    alert: &Alert,
+    observations: &[Observation],
+    state: AlertState,
+) -> String {
+    let context = alert_context(alert, observations, state);
+    let rendered = match render_context(text, context, false) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            eprintln!("Failed to render template for alert {}: {}", alert.id, e);
+            text.to_string()
+        }
+    };
+    fill_placeholders(&rendered, alert, observations)
+}
+
+// `{metric}` is the first observed value, `{<metric_id>}` the value of that specific metric.
+// `{baseline_mean}`, `{baseline_stddev}` and `{zscore}` come from the first anomaly condition.
+fn fill_placeholders(text: &str, alert: &Alert, observations: &[Observation]) -> String {
+    let text = text
+        .replace("{alert_id}", &alert.id.to_string())
+        .replace("{severity}", &alert.severity.to_string());
+    let mut text = match observations.first() {
+        Some(observation) => text.replace("{metric}", &observation.value.to_string()),
+        None => text.to_string(),
+    };
+    if let Some(baseline) = observations.iter().find_map(|o| o.baseline.as_ref()) {
+        text = text
+            .replace("{baseline_mean}", &baseline.mean.to_string())
+            .replace("{baseline_stddev}", &baseline.stddev.to_string())
+            .replace("{zscore}", &baseline.zscore.to_string());
+    }
+    for observation in observations {
+        text = text.replace(
+            &format!("{{{}}}", observation.metric_id),
+            &observation.value.to_string(),
+        );
+    }
+    text
+}
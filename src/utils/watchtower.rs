@@ -1,136 +1,1018 @@
 use sqlx::SqlitePool;
 
-use crate::models::{alert::Alert, metrics};
+use crate::models::{
+    alert::{Absence, Alert, Anomaly, Change, Condition, Forecast, Observation, Threshold},
+    channel::{Channel, ChannelKind},
+    escalation::EscalationPolicy,
+    history::AlertEvent,
+    incident::{AlertState, EventKind, Incident, IncidentEvent},
+    metrics::{self, MetricType},
+    routing::NotificationRoute,
+    silence::Silence,
+};
 use std::{collections::HashMap, sync::Arc};
+use uuid::Uuid;
 
 use super::{
-    config::{self, EnvConfig},
-    db,
-    log::Logger,
+    collector::STARTED_AT, config::ConfigState, db, log::Logger, mqtt::Mqtt, notifier::Notifier,
 };
 
 pub struct Watchtower {
     pool: Arc<SqlitePool>,
-    env: EnvConfig,
+    config: ConfigState,
     logger: Logger,
+    notifier: Notifier,
+    mqtt: Option<Mqtt>,
+    // Time of the previous check, channel repeats are due when an interval boundary passed since
+    last_check: Option<i64>,
+    // Silence that suppressed each alert at the previous check, only a new one is logged
+    silenced: HashMap<Uuid, Uuid>,
 }
 
 impl Watchtower {
-    pub fn new(pool: Arc<SqlitePool>, env: EnvConfig, logger: Logger) -> Self {
-        Watchtower { pool, env, logger }
+    pub fn new(
+        pool: Arc<SqlitePool>,
+        config: ConfigState,
+        logger: Logger,
+        notifier: Notifier,
+        mqtt: Option<Mqtt>,
+    ) -> Self {
+        Watchtower {
+            pool,
+            config,
+            logger,
+            notifier,
+            mqtt,
+            last_check: None,
+            silenced: HashMap::new(),
+        }
     }
 
-    async fn get_alerts(&self) -> Vec<Alert> {
-        let mut alerts = config::AlertConfig::new(&self.env);
-        alerts.read_config().await;
-        alerts.get_alerts().await.clone()
+    async fn get_active_silences(&self) -> Vec<Silence> {
+        let now = chrono::Local::now();
+        match db::get_silences(&self.pool).await {
+            Ok(silences) => silences.into_iter().filter(|s| s.is_active(now)).collect(),
+            Err(e) => {
+                eprintln!("Failed to read silences: {:?}", e);
+                vec![]
+            }
+        }
     }
 
     pub async fn watch(&mut self) {
         loop {
-            let alerts = self.get_alerts().await;
-            for alert in alerts {
-                match metrics::get_metric_type_from_str(&alert.metric_id) {
-                    Some(metric_type) => {
-                        let metric = db::get_metric(&self.pool, metric_type).await;
-                        if let Ok(value) = alert.value.parse::<f32>() {
-                            if metric.check(value, alert.metric_id.clone(), alert.logic.clone()) {
-                                exec_alert(&alert, metric).await;
-                                if let Err(e) = self.logger.log(alert) {
-                                    eprintln!("Failed to log alert: {:?}", e);
-                                }
-                            }
-                        } else {
-                            eprintln!("Failed to parse value: {} as f32", alert.value);
+            self.check(chrono::Utc::now().timestamp()).await;
+            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+        }
+    }
+
+    // One pass over every rule, incidents are opened, escalated and resolved at `now`
+    async fn check(&mut self, now: i64) {
+        let alerts = self.config.alerts.get_all().await;
+        let channels = self.config.channels.get_all().await;
+        let routes = self.config.routing.get_all().await;
+        let policies = self.config.escalations.get_all().await;
+        let silences = self.get_active_silences().await;
+        let mut incidents = match db::get_open_incidents(&self.pool).await {
+            Ok(incidents) => incidents,
+            Err(e) => {
+                eprintln!("Failed to read open incidents: {:?}", e);
+                vec![]
+            }
+        };
+        for alert in &alerts {
+            let open = incidents
+                .iter()
+                .position(|i| i.alert_id == alert.id)
+                .map(|i| incidents.swap_remove(i));
+            let mut observations = Vec::new();
+            // Without data to decide on, the rule keeps its state until the next check
+            let Some(firing) = self.evaluate(&alert.condition(), &mut observations).await else {
+                continue;
+            };
+            if !firing && open.is_none() {
+                continue;
+            }
+            let route = routes.iter().find(|r| r.matches(alert));
+            let policy = alert.escalation_policy.and_then(|id| {
+                let policy = policies.iter().find(|p| p.id == id);
+                if policy.is_none() {
+                    eprintln!(
+                        "Alert {} references unknown escalation policy {}",
+                        alert.id, id
+                    );
+                }
+                policy
+            });
+            let (incident, opened) = match (firing, open) {
+                (false, None) => continue,
+                (false, Some(incident)) => {
+                    let targets = notified_targets(alert, &incident, policy, route, &channels);
+                    for target in targets.iter().filter(|t| t.sends_resolved()) {
+                        self.notifier
+                            .notify(target, alert, &observations, AlertState::Resolved);
+                    }
+                    self.resolve(&incident, Some(alert), &observations, now)
+                        .await;
+                    self.silenced.remove(&alert.id);
+                    if let Some(mqtt) = &self.mqtt {
+                        mqtt.publish_alert(alert, &observations, AlertState::Resolved);
+                    }
+                    continue;
+                }
+                (true, Some(incident)) => (incident, false),
+                (true, None) => match db::open_incident(&self.pool, alert.id, now).await {
+                    Ok(incident) => {
+                        self.record(&incident, EventKind::Fired, alert.to_string())
+                            .await;
+                        self.save_event(
+                            &incident,
+                            Some(alert),
+                            &observations,
+                            AlertState::Firing,
+                            now,
+                        )
+                        .await;
+                        if let Err(e) = self.logger.log_event("Firing", alert, &observations) {
+                            eprintln!("Failed to log alert: {:?}", e);
+                        }
+                        if let Some(mqtt) = &self.mqtt {
+                            mqtt.publish_alert(alert, &observations, AlertState::Firing);
+                        }
+                        (incident, true)
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to open incident for {}: {:?}", alert.id, e);
+                        continue;
+                    }
+                },
+            };
+
+            let silence = silences.iter().find(|s| s.matches(alert));
+            let silence_started = match silence {
+                Some(silence) => self.silenced.insert(alert.id, silence.id) != Some(silence.id),
+                None => {
+                    self.silenced.remove(&alert.id);
+                    false
+                }
+            };
+            // Silenced alerts are logged once per incident and silence, but no request is sent
+            let result = match (silence, policy) {
+                (Some(silence), _) if opened || silence_started => {
+                    self.record(&incident, EventKind::Suppressed, silence.to_string())
+                        .await;
+                    self.logger
+                        .log_suppressed(alert.clone(), &observations, silence)
+                }
+                (Some(_), _) => Ok(()),
+                // Acknowledged incidents stop notifying until they resolve
+                _ if incident.acknowledged_at.is_some() => Ok(()),
+                (None, Some(policy)) => {
+                    let steps = escalation_steps(alert, policy, route, &channels);
+                    // Webhooks of steps already sent fire on every check, like without a policy
+                    let mut repeats: Vec<ChannelKind> =
+                        notified_targets(alert, &incident, Some(policy), route, &channels)
+                            .into_iter()
+                            .filter(|t| t.repeats_every_check())
+                            .collect();
+                    for target in
+                        self.repeat_targets(alert, &incident, Some(policy), route, &channels, now)
+                    {
+                        if !repeats.contains(&target) {
+                            repeats.push(target);
                         }
                     }
-                    None => eprintln!("Invalid metric id: {}", alert.metric_id),
+                    for target in &repeats {
+                        self.notifier
+                            .notify(target, alert, &observations, AlertState::Firing);
+                    }
+                    self.escalate(alert, &incident, &steps, &observations, now)
+                        .await
                 }
+                (None, None) => {
+                    let targets = resolve_targets(alert, route, &channels);
+                    // Human facing channels hear about an incident once, plus their repeat_interval
+                    let mut sent: Vec<ChannelKind> = targets
+                        .iter()
+                        .filter(|t| incident.escalation_step == 0 || t.repeats_every_check())
+                        .cloned()
+                        .collect();
+                    for target in self.repeat_targets(alert, &incident, None, route, &channels, now)
+                    {
+                        if !sent.contains(&target) {
+                            sent.push(target);
+                        }
+                    }
+                    for target in &sent {
+                        self.notifier
+                            .notify(target, alert, &observations, AlertState::Firing);
+                    }
+                    if incident.escalation_step == 0 {
+                        let detail = format!("{} targets", targets.len());
+                        self.record(&incident, EventKind::Notified, detail).await;
+                        if let Err(e) = db::set_escalation_step(&self.pool, incident.id, 1).await {
+                            eprintln!("Failed to save escalation step of {}: {:?}", incident.id, e);
+                        }
+                    }
+                    if sent.is_empty() {
+                        Ok(())
+                    } else {
+                        self.logger.log(alert.clone(), &observations)
+                    }
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to log alert: {:?}", e);
             }
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
         }
+        // Incidents of deleted alerts have nothing left to fire
+        for incident in incidents {
+            self.resolve(&incident, None, &[], now).await;
+        }
+        self.notifier.flush();
+        self.last_check = Some(now);
     }
-}
 
-async fn exec_alert(alert: &Alert, metric: metrics::MetricType) {
-    match alert.request.request_type {
-        crate::models::request::RequestType::Get => {
-            let _ = reqwest::get(
-                alert.request.url.as_str().replace(
-                    "{metric}",
-                    metric
-                        .get_value(alert.metric_id.clone())
-                        .to_string()
-                        .as_str(),
-                ),
-            )
+    // Sends every step that became due since the last tick and persists the progress
+    async fn escalate(
+        &mut self,
+        alert: &Alert,
+        incident: &Incident,
+        steps: &[(u64, Vec<ChannelKind>)],
+        observations: &[Observation],
+        now: i64,
+    ) -> Result<(), std::io::Error> {
+        let elapsed = (now - incident.started_at).max(0) as u64;
+        let mut step = incident.escalation_step as usize;
+        while let Some((after, targets)) = steps.get(step)
+            && *after <= elapsed
+        {
+            for target in targets {
+                self.notifier
+                    .notify(target, alert, observations, AlertState::Firing);
+            }
+            let detail = format!("Escalation step {} ({} targets)", step, targets.len());
+            self.record(incident, EventKind::Notified, detail).await;
+            self.logger
+                .log_event(&format!("Escalation step {}", step), alert, observations)?;
+            step += 1;
+        }
+        if step != incident.escalation_step as usize
+            && let Err(e) = db::set_escalation_step(&self.pool, incident.id, step as u32).await
+        {
+            eprintln!("Failed to save escalation step of {}: {:?}", incident.id, e);
+        }
+        Ok(())
+    }
+
+    // Channels already notified for the incident whose `repeat_interval` came around since
+    // the previous check. Plain webhooks are left out, both branches send them on every check.
+    fn repeat_targets(
+        &self,
+        alert: &Alert,
+        incident: &Incident,
+        policy: Option<&EscalationPolicy>,
+        route: Option<&NotificationRoute>,
+        channels: &[Channel],
+        now: i64,
+    ) -> Vec<ChannelKind> {
+        let Some(previous) = self.last_check else {
+            return vec![];
+        };
+        if incident.escalation_step == 0 {
+            return vec![];
+        }
+        let mut ids = alert.channels.clone();
+        if let Some(route) = route {
+            ids.extend(route.channels.iter().copied());
+        }
+        // Step 0 is the rule's own targets, the policy steps follow it
+        if let Some(policy) = policy {
+            ids.extend(
+                policy
+                    .steps
+                    .iter()
+                    .take(incident.escalation_step as usize - 1)
+                    .flat_map(|step| step.channels.iter().copied()),
+            );
+        }
+        let elapsed = |t: i64| (t - incident.started_at).max(0) as u64;
+        channels
+            .iter()
+            .filter(|c| ids.contains(&c.id) && !c.kind.repeats_every_check())
+            .filter(|c| {
+                c.repeat_interval
+                    .filter(|interval| *interval > 0)
+                    .is_some_and(|interval| elapsed(now) / interval > elapsed(previous) / interval)
+            })
+            .map(|c| c.kind.clone())
+            .collect()
+    }
+
+    async fn record(&self, incident: &Incident, kind: EventKind, detail: String) {
+        let event = IncidentEvent::new(kind, detail);
+        if let Err(e) = db::add_incident_event(&self.pool, incident.id, &event).await {
+            eprintln!(
+                "Failed to record event of incident {}: {:?}",
+                incident.id, e
+            );
+        }
+    }
+
+    // Alert history for `/alerts/history`, kept for the metrics retention period
+    async fn save_event(
+        &self,
+        incident: &Incident,
+        alert: Option<&Alert>,
+        observations: &[Observation],
+        state: AlertState,
+        now: i64,
+    ) {
+        let observation = observations.first();
+        let event = AlertEvent {
+            alert_id: incident.alert_id,
+            incident_id: incident.id,
+            state,
+            timestamp: now,
+            severity: alert.map(|a| a.severity.to_string()),
+            metric_id: observation
+                .map(|o| o.metric_id.clone())
+                .or_else(|| alert.map(|a| a.metric_id.clone())),
+            value: observation.map(|o| o.value),
+        };
+        if let Err(e) = db::insert_alert_event(&self.pool, &event).await {
+            eprintln!(
+                "Failed to save history of incident {}: {:?}",
+                incident.id, e
+            );
+        }
+    }
+
+    async fn resolve(
+        &mut self,
+        incident: &Incident,
+        alert: Option<&Alert>,
+        observations: &[Observation],
+        now: i64,
+    ) {
+        if let Err(e) = db::resolve_incident(&self.pool, incident.id, now).await {
+            eprintln!("Failed to resolve incident {}: {:?}", incident.id, e);
+            return;
+        }
+        let detail = match alert {
+            Some(_) => String::new(),
+            None => "Alert removed".to_string(),
+        };
+        self.record(incident, EventKind::Resolved, detail).await;
+        self.save_event(incident, alert, observations, AlertState::Resolved, now)
             .await;
+        if let Some(alert) = alert
+            && let Err(e) = self.logger.log_event("Resolved", alert, &[])
+        {
+            eprintln!("Failed to log alert: {:?}", e);
         }
-        crate::models::request::RequestType::Post => match alert.request.body.format {
-            crate::models::request::BodyFormat::Json => {
-                let _ = reqwest::Client::new()
-                    .post(
-                        alert.request.url.as_str().replace(
-                            "{metric}",
-                            metric
-                                .get_value(alert.metric_id.clone())
-                                .to_string()
-                                .as_str(),
-                        ),
-                    )
-                    .json(
-                        &alert.request.body.payload.replace(
-                            "{metric}",
-                            metric
-                                .get_value(alert.metric_id.clone())
-                                .to_string()
-                                .as_str(),
-                        ),
-                    )
-                    .send()
+    }
+
+    // Every branch is evaluated so all metric values end up in `observations`. `None` means
+    // there was no data to decide on, a group is only undecided if the known parts don't settle it.
+    async fn evaluate(
+        &self,
+        condition: &Condition,
+        observations: &mut Vec<Observation>,
+    ) -> Option<bool> {
+        match condition {
+            Condition::Threshold(threshold) => self.check_threshold(threshold, observations).await,
+            Condition::Rate(change) => self.check_change(change, metrics::rate, observations).await,
+            Condition::Delta(change) => {
+                self.check_change(change, metrics::delta, observations)
                     .await
-                    .map_err(|e| {
-                        eprintln!("Failed to send request: {:?}", e);
-                    });
-            }
-            crate::models::request::BodyFormat::XWwwFormUrlEncoded => {
-                let form = alert
-                    .request
-                    .body
-                    .payload
-                    .replace(
-                        "{metric}",
-                        metric
-                            .get_value(alert.metric_id.clone())
-                            .to_string()
-                            .as_str(),
-                    )
-                    .split('&')
-                    .map(|kv| {
-                        let mut split = kv.split('=');
-                        (
-                            split.next().unwrap_or("").to_string(),
-                            split.next().unwrap_or("").to_string(),
-                        )
-                    })
-                    .collect::<HashMap<String, String>>();
-                let _ = reqwest::Client::new()
-                    .post(
-                        alert.request.url.as_str().replace(
-                            "{metric}",
-                            metric
-                                .get_value(alert.metric_id.clone())
-                                .to_string()
-                                .as_str(),
-                        ),
-                    )
-                    .form(&form)
-                    .send()
+            }
+            Condition::Forecast(forecast) => self.check_forecast(forecast, observations).await,
+            Condition::Anomaly(anomaly) => self.check_anomaly(anomaly, observations).await,
+            Condition::Absence(absence) => self.check_absence(absence, observations).await,
+            Condition::And(conditions) => {
+                let mut results = Vec::new();
+                for condition in conditions {
+                    results.push(Box::pin(self.evaluate(condition, observations)).await);
+                }
+                metrics::all(&results)
+            }
+            Condition::Or(conditions) => {
+                let mut results = Vec::new();
+                for condition in conditions {
+                    results.push(Box::pin(self.evaluate(condition, observations)).await);
+                }
+                metrics::any(&results)
+            }
+            Condition::Not(condition) => Box::pin(self.evaluate(condition, observations))
+                .await
+                .map(|result| !result),
+        }
+    }
+
+    async fn check_threshold(
+        &self,
+        threshold: &Threshold,
+        observations: &mut Vec<Observation>,
+    ) -> Option<bool> {
+        let metric_type = match metrics::get_metric_type_from_str(&threshold.metric_id) {
+            Some(metric_type) => metric_type,
+            None => {
+                eprintln!("Invalid metric id: {}", threshold.metric_id);
+                return None;
+            }
+        };
+        let value = match threshold.value.parse::<f32>() {
+            Ok(value) => value,
+            Err(_) => {
+                eprintln!("Failed to parse value: {} as f32", threshold.value);
+                return None;
+            }
+        };
+        // A metric that never reported cannot cross a threshold, not even `< 1`
+        let metric = db::get_metric(&self.pool, metric_type).await?;
+        observations.push(Observation {
+            metric_id: threshold.metric_id.clone(),
+            value: metric.get_value(threshold.metric_id.clone()),
+            baseline: None,
+        });
+        Some(metric.check(value, threshold.metric_id.clone(), threshold.logic.clone()))
+    }
+
+    async fn check_change(
+        &self,
+        change: &Change,
+        compute: fn(&[(i64, f32)]) -> Option<f32>,
+        observations: &mut Vec<Observation>,
+    ) -> Option<bool> {
+        let metric_type = match metrics::get_metric_type_from_str(&change.metric_id) {
+            Some(metric_type) => metric_type,
+            None => {
+                eprintln!("Invalid metric id: {}", change.metric_id);
+                return None;
+            }
+        };
+        let value = match change.value.parse::<f32>() {
+            Ok(value) => value,
+            Err(_) => {
+                eprintln!("Failed to parse value: {} as f32", change.value);
+                return None;
+            }
+        };
+        let now = chrono::Utc::now().timestamp();
+        let since = now - change.window as i64;
+        let history =
+            match db::get_metric_history(&self.pool, metric_type, &change.metric_id, since, now)
+                .await
+            {
+                Ok(history) => history,
+                Err(e) => {
+                    eprintln!("Failed to read history for {}: {:?}", change.metric_id, e);
+                    return None;
+                }
+            };
+        // Not enough samples in the window yet, undecided rather than cleared
+        let observed = compute(&history)?;
+        observations.push(Observation {
+            metric_id: change.metric_id.clone(),
+            value: observed,
+            baseline: None,
+        });
+        Some(change.logic.check(observed, value))
+    }
+
+    async fn check_forecast(
+        &self,
+        forecast: &Forecast,
+        observations: &mut Vec<Observation>,
+    ) -> Option<bool> {
+        let metric_type = match metrics::get_metric_type_from_str(&forecast.metric_id) {
+            Some(metric_type) => metric_type,
+            None => {
+                eprintln!("Invalid metric id: {}", forecast.metric_id);
+                return None;
+            }
+        };
+        let (Ok(target), Ok(value)) = (
+            forecast.target.parse::<f32>(),
+            forecast.value.parse::<f32>(),
+        ) else {
+            eprintln!("Failed to parse forecast values for {}", forecast.metric_id);
+            return None;
+        };
+        let now = chrono::Utc::now().timestamp();
+        let since = now - forecast.window as i64;
+        let history = match &forecast.mount_point {
+            Some(mount_point) => db::get_mount_history(&self.pool, mount_point, since)
+                .await
+                .map(|history| {
+                    history
+                        .into_iter()
+                        .map(|(t, m)| {
+                            (t, MetricType::Disk(m).get_value(forecast.metric_id.clone()))
+                        })
+                        .collect()
+                }),
+            None => {
+                db::get_metric_history(&self.pool, metric_type, &forecast.metric_id, since, now)
                     .await
-                    .map_err(|e| {
-                        eprintln!("Failed to send request: {:?}", e);
-                    });
             }
+        };
+        let history: Vec<(i64, f32)> = match history {
+            Ok(history) => history,
+            Err(e) => {
+                eprintln!("Failed to read history for {}: {:?}", forecast.metric_id, e);
+                return None;
+            }
+        };
+        // Without a trend yet the rule keeps its state, flat or receding trends never fire
+        if history.len() < 2 {
+            return None;
+        }
+        let Some(hours) = metrics::hours_until(&history, target) else {
+            return Some(false);
+        };
+        observations.push(Observation {
+            metric_id: forecast.metric_id.clone(),
+            value: hours,
+            baseline: None,
+        });
+        Some(forecast.logic.check(hours, value))
+    }
+
+    async fn check_anomaly(
+        &self,
+        anomaly: &Anomaly,
+        observations: &mut Vec<Observation>,
+    ) -> Option<bool> {
+        let metric_type = match metrics::get_metric_type_from_str(&anomaly.metric_id) {
+            Some(metric_type) => metric_type,
+            None => {
+                eprintln!("Invalid metric id: {}", anomaly.metric_id);
+                return None;
+            }
+        };
+        let now = chrono::Utc::now().timestamp();
+        let window = anomaly.window as i64;
+        let current = db::get_metric(&self.pool, metric_type.clone())
+            .await?
+            .get_value(anomaly.metric_id.clone());
+
+        let mut samples = Vec::new();
+        for (since, until) in metrics::baseline_ranges(now, window, anomaly.seasonal) {
+            match db::get_metric_history(
+                &self.pool,
+                metric_type.clone(),
+                &anomaly.metric_id,
+                since,
+                until,
+            )
+            .await
+            {
+                Ok(history) => samples.extend(history.into_iter().map(|(_, v)| v)),
+                Err(e) => {
+                    eprintln!("Failed to read history for {}: {:?}", anomaly.metric_id, e);
+                    return None;
+                }
+            }
+        }
+
+        // Too little history keeps the rule's state, a flat baseline has no meaningful deviation
+        if samples.len() < 2 {
+            return None;
+        }
+        let Some(baseline) = metrics::baseline(current, &samples) else {
+            return Some(false);
+        };
+        let zscore = baseline.zscore;
+        observations.push(Observation {
+            metric_id: anomaly.metric_id.clone(),
+            value: current,
+            baseline: Some(baseline),
+        });
+        Some(zscore.abs() > anomaly.sigma)
+    }
+
+    async fn check_absence(
+        &self,
+        absence: &Absence,
+        observations: &mut Vec<Observation>,
+    ) -> Option<bool> {
+        let metric_type = match metrics::get_metric_type_from_str(&absence.metric_id) {
+            Some(metric_type) => metric_type,
+            None => {
+                eprintln!("Invalid metric id: {}", absence.metric_id);
+                return None;
+            }
+        };
+        let last = match db::get_last_timestamp(&self.pool, &metric_type).await {
+            Ok(last) => last,
+            Err(e) => {
+                eprintln!(
+                    "Failed to read last sample of {}: {:?}",
+                    absence.metric_id, e
+                );
+                return None;
+            }
+        };
+        // Pushes fail while sciigilo is down, so silence is only counted from the process start
+        let age = chrono::Utc::now().timestamp() - last.unwrap_or(0).max(*STARTED_AT);
+        observations.push(Observation {
+            metric_id: absence.metric_id.clone(),
+            value: age as f32,
+            baseline: None,
+        });
+        Some(age > absence.window as i64)
+    }
+}
+
+// The rule's channels and inline request, plus those of the first matching route
+fn resolve_targets(
+    alert: &Alert,
+    route: Option<&NotificationRoute>,
+    channels: &[Channel],
+) -> Vec<ChannelKind> {
+    let mut channel_ids = alert.channels.clone();
+    let mut targets: Vec<ChannelKind> = alert
+        .request
+        .iter()
+        .cloned()
+        .map(ChannelKind::Webhook)
+        .chain(alert.exec.iter().cloned().map(ChannelKind::Exec))
+        .collect();
+    if let Some(route) = route {
+        channel_ids.extend(
+            route
+                .channels
+                .iter()
+                .filter(|id| !alert.channels.contains(id)),
+        );
+        targets.extend(route.request.iter().cloned().map(ChannelKind::Webhook));
+    }
+    targets.extend(lookup_channels(alert, &channel_ids, channels));
+    targets
+}
+
+// Step 0 notifies the rule's own targets right away, the policy steps follow
+fn escalation_steps(
+    alert: &Alert,
+    policy: &EscalationPolicy,
+    route: Option<&NotificationRoute>,
+    channels: &[Channel],
+) -> Vec<(u64, Vec<ChannelKind>)> {
+    let mut steps = vec![(0, resolve_targets(alert, route, channels))];
+    steps.extend(
+        policy
+            .steps
+            .iter()
+            .map(|step| (step.after, lookup_channels(alert, &step.channels, channels))),
+    );
+    steps
+}
+
+// Everything an incident has notified so far, each target once
+pub fn notified_targets(
+    alert: &Alert,
+    incident: &Incident,
+    policy: Option<&EscalationPolicy>,
+    route: Option<&NotificationRoute>,
+    channels: &[Channel],
+) -> Vec<ChannelKind> {
+    let notified: Vec<ChannelKind> = match policy {
+        Some(policy) => escalation_steps(alert, policy, route, channels)
+            .into_iter()
+            .take(incident.escalation_step as usize)
+            .flat_map(|(_, targets)| targets)
+            .collect(),
+        None if incident.escalation_step > 0 => resolve_targets(alert, route, channels),
+        None => vec![],
+    };
+    let mut targets = Vec::new();
+    for target in notified {
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+fn lookup_channels(alert: &Alert, ids: &[Uuid], channels: &[Channel]) -> Vec<ChannelKind> {
+    ids.iter()
+        .filter_map(|id| match channels.iter().find(|c| c.id == *id) {
+            Some(channel) => Some(channel.kind.clone()),
+            None => {
+                eprintln!("Alert {} references unknown channel {}", alert.id, id);
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{Router, http::Uri};
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    use super::*;
+    use crate::{
+        models::{
+            alert::Logic,
+            channel::Slack,
+            custom::CustomMetric,
+            escalation::EscalationStep,
+            request::{Body, BodyFormat, Request, RequestType},
         },
+        utils::config::EnvConfig,
+    };
+
+    // A watchtower with an in-memory database and its own config and log files
+    async fn watchtower() -> (Watchtower, ConfigState) {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let pool = Arc::new(pool);
+        db::init_db(&pool, 1).await;
+        let dir = std::env::temp_dir().join(format!("sciigilo-watchtower-{}", Uuid::new_v4()));
+        let path = |name: &str| dir.join(name).display().to_string();
+        let mut env = EnvConfig::new();
+        env.alerts_file_path = path("alerts.json");
+        env.routing_file_path = path("routing.json");
+        env.channels_file_path = path("channels.json");
+        env.escalations_file_path = path("escalations.json");
+        env.log_file_path = path("sciigilo.log");
+        env.http_retries = 0;
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = ConfigState::read_config(&env).await;
+        let logger = Logger::new(&env).unwrap();
+        let notifier = Notifier::new(pool.clone(), &env, logger.clone());
+        let watchtower = Watchtower::new(pool, config.clone(), logger, notifier, None);
+        (watchtower, config)
+    }
+
+    // Answers every request and passes on its path
+    async fn stub() -> (String, mpsc::UnboundedReceiver<String>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let app = Router::new().fallback(move |uri: Uri| {
+            let sender = sender.clone();
+            async move {
+                sender.send(uri.path().to_string()).unwrap();
+                "ok"
+            }
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, receiver)
+    }
+
+    // Paths requested until the stub stays quiet
+    async fn received(receiver: &mut mpsc::UnboundedReceiver<String>) -> Vec<String> {
+        let mut paths = Vec::new();
+        while let Ok(Some(path)) =
+            tokio::time::timeout(Duration::from_millis(500), receiver.recv()).await
+        {
+            paths.push(path);
+        }
+        paths.sort();
+        paths
+    }
+
+    fn webhook(url: String) -> ChannelKind {
+        ChannelKind::Webhook(Request {
+            request_type: RequestType::Post,
+            url,
+            body: Body::new(BodyFormat::Json, "{}".to_string()),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn escalation_progresses_until_acknowledged() {
+        let (mut watchtower, config) = watchtower().await;
+        let (url, mut requests) = stub().await;
+        let own = Channel {
+            id: Uuid::new_v4(),
+            name: "own".to_string(),
+            kind: webhook(format!("{}/own", url)),
+            ..Default::default()
+        };
+        let first = Channel {
+            id: Uuid::new_v4(),
+            name: "first".to_string(),
+            kind: ChannelKind::Slack(Slack {
+                webhook_url: format!("{}/first", url),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let second = Channel {
+            id: Uuid::new_v4(),
+            name: "second".to_string(),
+            kind: webhook(format!("{}/second", url)),
+            ..Default::default()
+        };
+        let policy = EscalationPolicy {
+            id: Uuid::new_v4(),
+            name: "on-call".to_string(),
+            steps: vec![
+                EscalationStep {
+                    after: 600,
+                    channels: vec![first.id],
+                },
+                EscalationStep {
+                    after: 1200,
+                    channels: vec![second.id],
+                },
+            ],
+        };
+        let alert = Alert {
+            id: Uuid::new_v4(),
+            metric_id: "custom_temp".to_string(),
+            logic: Logic::Gt,
+            value: "50".to_string(),
+            channels: vec![own.id],
+            escalation_policy: Some(policy.id),
+            ..Default::default()
+        };
+        config.channels.set_all(vec![own, first, second]).await;
+        config.escalations.add(policy).await;
+        config.alerts.add(alert.clone()).await;
+        let metric = CustomMetric::new("temp".to_string(), 80.0);
+        db::insert_metrics(&watchtower.pool, MetricType::Custom(metric))
+            .await
+            .unwrap();
+        let incident = async |watchtower: &Watchtower| {
+            db::get_open_incidents(&watchtower.pool)
+                .await
+                .unwrap()
+                .pop()
+                .unwrap()
+        };
+
+        // Step 0 right away, its webhook again on every check
+        let start = chrono::Utc::now().timestamp();
+        watchtower.check(start).await;
+        assert_eq!(incident(&watchtower).await.escalation_step, 1);
+        watchtower.check(start + 5).await;
+        assert_eq!(received(&mut requests).await, ["/own", "/own"]);
+
+        // The first step once it is due, the webhook keeps repeating
+        watchtower.check(start + 600).await;
+        assert_eq!(incident(&watchtower).await.escalation_step, 2);
+        assert_eq!(received(&mut requests).await, ["/first", "/own"]);
+
+        // Acknowledging stops every notification, including the second step
+        db::acknowledge_incident(
+            &watchtower.pool,
+            &alert.id.to_string(),
+            start + 700,
+            None,
+            None,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        watchtower.check(start + 1200).await;
+        let open = incident(&watchtower).await;
+        assert_eq!(open.escalation_step, 2);
+        assert!(received(&mut requests).await.is_empty());
+
+        let notified = db::get_incident_events(&watchtower.pool, open.id)
+            .await
+            .unwrap()
+            .iter()
+            .filter(|e| e.kind == EventKind::Notified)
+            .count();
+        assert_eq!(notified, 2);
+    }
+
+    #[tokio::test]
+    async fn missing_data_keeps_the_incident_open() {
+        let (mut watchtower, config) = watchtower().await;
+        let alert = Alert {
+            id: Uuid::new_v4(),
+            condition: Some(Condition::Delta(Change {
+                metric_id: "custom_queue".to_string(),
+                window: 60,
+                logic: Logic::Gt,
+                value: "10".to_string(),
+            })),
+            ..Default::default()
+        };
+        config.alerts.add(alert).await;
+        let now = chrono::Utc::now().timestamp();
+        for (timestamp, value) in [(now - 10, 50.0), (now, 80.0)] {
+            sqlx::query(
+                "INSERT INTO CustomMetrics (timestamp, name, value) VALUES (datetime(?, 'unixepoch'), ?, ?)",
+            )
+            .bind(timestamp)
+            .bind("queue")
+            .bind(value)
+            .execute(&*watchtower.pool)
+            .await
+            .unwrap();
+        }
+        watchtower.check(now).await;
+        let incidents = db::get_open_incidents(&watchtower.pool).await.unwrap();
+        assert_eq!(incidents.len(), 1);
+
+        // A gap in the data is not the condition clearing
+        sqlx::query("DELETE FROM CustomMetrics")
+            .execute(&*watchtower.pool)
+            .await
+            .unwrap();
+        watchtower.check(now + 5).await;
+        assert_eq!(
+            db::get_open_incidents(&watchtower.pool).await.unwrap(),
+            incidents
+        );
+    }
+
+    #[tokio::test]
+    async fn metrics_that_never_reported_do_not_fire() {
+        let (mut watchtower, config) = watchtower().await;
+        let conditions = [
+            Condition::Threshold(Threshold {
+                metric_id: "custom_workers".to_string(),
+                logic: Logic::Lt,
+                value: "1".to_string(),
+            }),
+            Condition::Absence(Absence {
+                metric_id: "custom_backup".to_string(),
+                window: 60,
+            }),
+        ];
+        for condition in conditions {
+            let alert = Alert {
+                id: Uuid::new_v4(),
+                condition: Some(condition),
+                ..Default::default()
+            };
+            config.alerts.add(alert).await;
+        }
+        watchtower.check(chrono::Utc::now().timestamp()).await;
+        assert!(
+            db::get_open_incidents(&watchtower.pool)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn silenced_alerts_are_logged_once_per_silence() {
+        let (mut watchtower, config) = watchtower().await;
+        let alert = Alert {
+            id: Uuid::new_v4(),
+            condition: Some(Condition::Threshold(Threshold {
+                metric_id: "custom_queue".to_string(),
+                logic: Logic::Gt,
+                value: "10".to_string(),
+            })),
+            ..Default::default()
+        };
+        config.alerts.add(alert.clone()).await;
+        db::insert_metrics(
+            &watchtower.pool,
+            MetricType::Custom(CustomMetric::new("queue".to_string(), 50.0)),
+        )
+        .await
+        .unwrap();
+        let now = chrono::Utc::now().timestamp();
+        watchtower.check(now).await;
+
+        let silence = Silence {
+            id: Uuid::new_v4(),
+            alert_ids: vec![alert.id],
+            starts_at: Some(now - 60),
+            ends_at: Some(now + 3600),
+            ..Default::default()
+        };
+        db::save_silence(&watchtower.pool, &silence).await.unwrap();
+        for tick in 1..4 {
+            watchtower.check(now + tick * 5).await;
+        }
+        let suppressed = |watchtower: &Watchtower| {
+            let lines = watchtower.logger.get(100).unwrap();
+            lines.iter().filter(|l| l.contains("Suppressed by")).count()
+        };
+        assert_eq!(suppressed(&watchtower), 1);
+
+        // A new silence over the same incident is worth another line
+        db::delete_silence(&watchtower.pool, &silence.id.to_string())
+            .await
+            .unwrap();
+        let silence = Silence {
+            id: Uuid::new_v4(),
+            ..silence
+        };
+        db::save_silence(&watchtower.pool, &silence).await.unwrap();
+        watchtower.check(now + 20).await;
+        watchtower.check(now + 25).await;
+        assert_eq!(suppressed(&watchtower), 2);
     }
 }